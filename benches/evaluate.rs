@@ -0,0 +1,41 @@
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use evaluator_rs::*;
+use std::collections::HashMap;
+
+const EXPRS: &[(&str, &str)] = &[
+    ("arithmetic", "({a} + {b}) * 2 - {c} / 4 > 10"),
+    ("logical", "{a} > 1 && {b} < 100 || {c} == 3"),
+    ("conditional", "if {a} > {b} then {a} - {b} else {b} - {a}"),
+    ("membership", "{c} in [1, 2, 3, 5, 8, 13]"),
+    ("call", "max({a}, {b}, {c}) + abs({a} - {c})"),
+];
+
+fn bench_evaluate(c: &mut Criterion) {
+    let parameters = HashMap::from([
+        ("a", Value::from(7)),
+        ("b", Value::from(42)),
+        ("c", Value::from(3)),
+    ]);
+
+    for (name, expr) in EXPRS {
+        let expr = parse_expr_from_str(expr).unwrap();
+        let compiled = compile(&expr);
+        let values: Vec<Value> = compiled
+            .slots()
+            .iter()
+            .map(|slot| parameters[slot.as_str()].clone())
+            .collect();
+
+        let mut group = c.benchmark_group(*name);
+        group.bench_function("tree", |b| {
+            b.iter(|| evaluate(black_box(&expr), black_box(&parameters)))
+        });
+        group.bench_function("compiled", |b| {
+            b.iter(|| compiled.evaluate(black_box(&values)))
+        });
+        group.finish();
+    }
+}
+
+criterion_group!(benches, bench_evaluate);
+criterion_main!(benches);
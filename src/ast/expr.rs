@@ -1,21 +1,359 @@
-use crate::ast::op::Op;
+use crate::ast::op::{Op, UnaryOp};
 use crate::ast::value::Value;
+use crate::parser::parser::{grammar::IdentifierParser, Builder};
+use serde::Serialize;
+use std::borrow::Cow;
+use std::collections::BTreeSet;
 use std::fmt::{Debug, Display, Error, Formatter};
 
 #[derive(Debug, PartialEq, PartialOrd)]
 pub enum Expr {
     Identifier(String),
+    Path(String, Vec<PathSegment>),
     Value(Value),
     Op(Box<Expr>, Op, Box<Expr>),
+    Unary(UnaryOp, Box<Expr>),
+    Call(String, Vec<Expr>),
+    Conditional(Box<Expr>, Box<Expr>, Box<Expr>),
 }
 
+/// One step of a nested field access such as `{user.address.city}` or `{items[0]}`.
+#[derive(Debug, Clone, PartialEq, PartialOrd)]
+pub enum PathSegment {
+    Field(String),
+    Index(usize),
+}
+
+/// Step from an expression to one of its operands, named after the keys of the JSON expression
+/// format.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum NodeStep {
+    Lhs,
+    Rhs,
+    Arg(usize),
+    If,
+    Then,
+    Else,
+}
+
+/// Location of a subexpression as steps from the root; displayed as a JSON pointer such as
+/// `/lhs/args/0`, which is also its location in the JSON expression format. The root is
+/// displayed as `/`.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct NodePath(pub Vec<NodeStep>);
+
+impl Display for NodeStep {
+    fn fmt(&self, fmt: &mut Formatter) -> Result<(), Error> {
+        match self {
+            NodeStep::Lhs => write!(fmt, "lhs"),
+            NodeStep::Rhs => write!(fmt, "rhs"),
+            NodeStep::Arg(i) => write!(fmt, "args/{}", i),
+            NodeStep::If => write!(fmt, "if"),
+            NodeStep::Then => write!(fmt, "then"),
+            NodeStep::Else => write!(fmt, "else"),
+        }
+    }
+}
+
+impl Display for NodePath {
+    fn fmt(&self, fmt: &mut Formatter) -> Result<(), Error> {
+        if self.0.is_empty() {
+            return write!(fmt, "/");
+        }
+        for step in &self.0 {
+            write!(fmt, "/{}", step)?;
+        }
+        Ok(())
+    }
+}
+
+impl Expr {
+    /// Subexpression at `path`
+    pub fn node(&self, path: &NodePath) -> Option<&Expr> {
+        path.0
+            .iter()
+            .try_fold(self, |expr, step| match (expr, step) {
+                (Expr::Op(lhs, _, _), NodeStep::Lhs) => Some(lhs.as_ref()),
+                (Expr::Op(_, _, rhs), NodeStep::Rhs) | (Expr::Unary(_, rhs), NodeStep::Rhs) => {
+                    Some(rhs.as_ref())
+                }
+                (Expr::Call(_, args), NodeStep::Arg(i)) => args.get(*i),
+                (Expr::Conditional(cond, _, _), NodeStep::If) => Some(cond.as_ref()),
+                (Expr::Conditional(_, then, _), NodeStep::Then) => Some(then.as_ref()),
+                (Expr::Conditional(_, _, otherwise), NodeStep::Else) => Some(otherwise.as_ref()),
+                _ => None,
+            })
+    }
+
+    /// Names of the identifiers referenced by the expression, including the roots of paths
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use evaluator_rs::*;
+    ///
+    /// let expr = parse_expr_from_str("{a} > 1 && len({user.tags}) > {a}").unwrap();
+    /// assert_eq!(expr.identifiers().into_iter().collect::<Vec<_>>(), ["a", "user"]);
+    /// ```
+    pub fn identifiers(&self) -> BTreeSet<&str> {
+        let mut names = BTreeSet::new();
+        self.visit(|expr, _| match expr {
+            Expr::Identifier(name) | Expr::Path(name, _) => {
+                names.insert(name.as_str());
+            }
+            _ => {}
+        });
+        names
+    }
+
+    /// Names of the functions called by the expression
+    pub fn functions(&self) -> BTreeSet<&str> {
+        let mut names = BTreeSet::new();
+        self.visit(|expr, _| {
+            if let Expr::Call(name, _) = expr {
+                names.insert(name.as_str());
+            }
+        });
+        names
+    }
+
+    /// Nested paths referenced by the expression, written like `user.tags[0]`
+    pub fn paths(&self) -> BTreeSet<String> {
+        let mut paths = BTreeSet::new();
+        self.visit(|expr, _| {
+            if let Expr::Path(name, segments) = expr {
+                paths.insert(segments.iter().fold(name.clone(), |mut path, s| {
+                    path.push_str(&s.to_string());
+                    path
+                }));
+            }
+        });
+        paths
+    }
+
+    /// Call `f` on every subexpression with its path, parents before children and operands in
+    /// evaluation order
+    pub(crate) fn visit<'a, F: FnMut(&'a Expr, &[NodeStep])>(&'a self, mut f: F) {
+        let mut path = vec![];
+        // subexpression, length of the path of its parent and step from the parent
+        let mut stack = vec![(self, 0, None)];
+        while let Some((expr, parent, step)) = stack.pop() {
+            path.truncate(parent);
+            path.extend(step);
+            f(expr, &path);
+
+            let depth = path.len();
+            match expr {
+                Expr::Identifier(_) | Expr::Path(_, _) | Expr::Value(_) => {}
+                Expr::Op(lhs, _, rhs) => stack.extend([
+                    (rhs.as_ref(), depth, Some(NodeStep::Rhs)),
+                    (lhs.as_ref(), depth, Some(NodeStep::Lhs)),
+                ]),
+                Expr::Unary(_, rhs) => stack.push((rhs, depth, Some(NodeStep::Rhs))),
+                Expr::Call(_, args) => stack.extend(
+                    args.iter()
+                        .enumerate()
+                        .rev()
+                        .map(|(i, arg)| (arg, depth, Some(NodeStep::Arg(i)))),
+                ),
+                Expr::Conditional(cond, then, otherwise) => stack.extend([
+                    (otherwise.as_ref(), depth, Some(NodeStep::Else)),
+                    (then.as_ref(), depth, Some(NodeStep::Then)),
+                    (cond.as_ref(), depth, Some(NodeStep::If)),
+                ]),
+            }
+        }
+    }
+}
+
+/// Drop operands with an explicit stack, as the default recursive drop overflows the stack on
+/// deeply nested expressions
+impl Drop for Expr {
+    fn drop(&mut self) {
+        let mut stack = vec![];
+        take_operands(self, &mut stack);
+        while let Some(mut expr) = stack.pop() {
+            take_operands(&mut expr, &mut stack);
+        }
+    }
+}
+
+/// Move the operands of `expr` to `stack`, leaving leaves in their place
+fn take_operands(expr: &mut Expr, stack: &mut Vec<Expr>) {
+    let mut take = |e: &mut Box<Expr>| stack.push(std::mem::replace(e, Expr::Value(Value::Null)));
+    match expr {
+        Expr::Identifier(_) | Expr::Path(_, _) | Expr::Value(_) => {}
+        Expr::Op(lhs, _, rhs) => {
+            take(lhs);
+            take(rhs);
+        }
+        Expr::Unary(_, rhs) => take(rhs),
+        Expr::Call(_, args) => stack.append(args),
+        Expr::Conditional(cond, then, otherwise) => {
+            take(cond);
+            take(then);
+            take(otherwise);
+        }
+    }
+}
+
+/// Written with an explicit stack, so expressions nested arbitrarily deep can be displayed
 impl Display for Expr {
     fn fmt(&self, fmt: &mut Formatter) -> Result<(), Error> {
         use self::Expr::*;
-        match &*self {
-            Identifier(v) => write!(fmt, "{{{}}}", v),
-            Value(v) => write!(fmt, "{}", v),
-            Op(ref l, op, ref r) => write!(fmt, "({} {} {})", l, op, r),
+
+        enum Piece<'a> {
+            Expr(&'a Expr),
+            Text(&'a dyn Display),
+        }
+
+        let mut stack = vec![Piece::Expr(self)];
+        while let Some(piece) = stack.pop() {
+            let expr = match piece {
+                Piece::Expr(expr) => expr,
+                Piece::Text(text) => {
+                    write!(fmt, "{}", text)?;
+                    continue;
+                }
+            };
+            match expr {
+                Identifier(v) => write!(fmt, "{{{}}}", v)?,
+                Path(v, segments) => {
+                    write!(fmt, "{{{}", v)?;
+                    for segment in segments {
+                        write!(fmt, "{}", segment)?;
+                    }
+                    write!(fmt, "}}")?
+                }
+                Value(v) => write!(fmt, "{}", v)?,
+                Op(l, op, r) => {
+                    write!(fmt, "(")?;
+                    stack.extend([
+                        Piece::Text(&")"),
+                        Piece::Expr(r),
+                        Piece::Text(&" "),
+                        Piece::Text(op),
+                        Piece::Text(&" "),
+                        Piece::Expr(l),
+                    ]);
+                }
+                Unary(op, r) => {
+                    write!(fmt, "{}", op)?;
+                    stack.push(Piece::Expr(r));
+                }
+                Call(name, args) => {
+                    write!(fmt, "{}(", name)?;
+                    stack.push(Piece::Text(&")"));
+                    for (i, arg) in args.iter().enumerate().rev() {
+                        stack.push(Piece::Expr(arg));
+                        if i > 0 {
+                            stack.push(Piece::Text(&", "));
+                        }
+                    }
+                }
+                Conditional(c, t, e) => {
+                    write!(fmt, "(if ")?;
+                    stack.extend([
+                        Piece::Text(&")"),
+                        Piece::Expr(e),
+                        Piece::Text(&" else "),
+                        Piece::Expr(t),
+                        Piece::Text(&" then "),
+                        Piece::Expr(c),
+                    ]);
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl Expr {
+    /// Write the expression in the JSON expression format read by `parse_expr_from_json`.
+    ///
+    /// Object values and strings written like an identifier (e.g. `"{a}"`) have no
+    /// representation in that format and are reported as an error. Written with an explicit
+    /// stack, so expressions nested arbitrarily deep can be serialized.
+    pub(crate) fn to_json(&self) -> Result<String, String> {
+        enum Piece<'a> {
+            Expr(&'a Expr),
+            Text(Cow<'static, str>),
+        }
+
+        let mut out = String::new();
+        let mut stack = vec![Piece::Expr(self)];
+        while let Some(piece) = stack.pop() {
+            let expr = match piece {
+                Piece::Expr(expr) => expr,
+                Piece::Text(text) => {
+                    out.push_str(&text);
+                    continue;
+                }
+            };
+            match expr {
+                Expr::Identifier(_) | Expr::Path(_, _) => out.push_str(&json(&expr.to_string())?),
+                Expr::Value(v @ Value::Object(_)) => {
+                    return Err(format!("object value {} has no JSON expression form", v))
+                }
+                Expr::Value(Value::String(v))
+                    if IdentifierParser::new()
+                        .parse(&Builder::default(), v)
+                        .is_ok() =>
+                {
+                    return Err(format!("string {} would be read as an identifier", v))
+                }
+                Expr::Value(v) => out.push_str(&json(v)?),
+                Expr::Op(lhs, op, rhs) => {
+                    out.push_str(r#"{"lhs":"#);
+                    stack.extend([
+                        Piece::Text("}".into()),
+                        Piece::Expr(rhs),
+                        Piece::Text(format!(r#","op":{},"rhs":"#, json(&op.to_string())?).into()),
+                        Piece::Expr(lhs),
+                    ]);
+                }
+                Expr::Unary(op, rhs) => {
+                    out.push_str(&format!(r#"{{"op":{},"rhs":"#, json(&op.to_string())?));
+                    stack.extend([Piece::Text("}".into()), Piece::Expr(rhs)]);
+                }
+                Expr::Call(name, args) => {
+                    out.push_str(&format!(r#"{{"call":{},"args":["#, json(name)?));
+                    stack.push(Piece::Text("]}".into()));
+                    for (i, arg) in args.iter().enumerate().rev() {
+                        stack.push(Piece::Expr(arg));
+                        if i > 0 {
+                            stack.push(Piece::Text(",".into()));
+                        }
+                    }
+                }
+                Expr::Conditional(cond, then, otherwise) => {
+                    out.push_str(r#"{"if":"#);
+                    stack.extend([
+                        Piece::Text("}".into()),
+                        Piece::Expr(otherwise),
+                        Piece::Text(r#","else":"#.into()),
+                        Piece::Expr(then),
+                        Piece::Text(r#","then":"#.into()),
+                        Piece::Expr(cond),
+                    ]);
+                }
+            }
+        }
+
+        Ok(out)
+    }
+}
+
+fn json<T: Serialize + ?Sized>(value: &T) -> Result<String, String> {
+    serde_json::to_string(value).map_err(|e| e.to_string())
+}
+
+impl Display for PathSegment {
+    fn fmt(&self, fmt: &mut Formatter) -> Result<(), Error> {
+        match self {
+            PathSegment::Field(v) => write!(fmt, ".{}", v),
+            PathSegment::Index(v) => write!(fmt, "[{}]", v),
         }
     }
 }
@@ -23,6 +361,7 @@ impl Display for Expr {
 #[cfg(test)]
 mod tests {
     use crate::ast::op::AdditiveOp;
+    use crate::test_util::{chain, EVALUATE_DEPTH, PASS_DEPTH};
 
     use super::*;
 
@@ -30,6 +369,45 @@ mod tests {
     fn test_display() {
         assert_eq!(format!("{}", Expr::Identifier("name".to_owned())), "{name}");
         assert_eq!(format!("{}", Expr::Value(Value::from(1.0))), "1");
+        assert_eq!(
+            format!(
+                "{}",
+                Expr::Path(
+                    "user".to_owned(),
+                    vec![
+                        PathSegment::Field("addresses".to_owned()),
+                        PathSegment::Index(0),
+                        PathSegment::Field("city".to_owned()),
+                    ]
+                )
+            ),
+            "{user.addresses[0].city}"
+        );
+        assert_eq!(
+            format!(
+                "{}",
+                Expr::Call(
+                    "max".to_owned(),
+                    vec![
+                        Expr::Identifier("a".to_owned()),
+                        Expr::Value(Value::from(1.0)),
+                    ]
+                )
+            ),
+            "max({a}, 1)"
+        );
+        assert_eq!(format!("{}", Expr::Call("now".to_owned(), vec![])), "now()");
+        assert_eq!(
+            format!(
+                "{}",
+                Expr::Conditional(
+                    Box::new(Expr::Identifier("vip".to_owned())),
+                    Box::new(Expr::Value(Value::from(0.2))),
+                    Box::new(Expr::Value(Value::from(0.0))),
+                )
+            ),
+            "(if {vip} then 0.2 else 0)"
+        );
         assert_eq!(
             format!(
                 "{}",
@@ -41,5 +419,120 @@ mod tests {
             ),
             "(1 + 1)",
         );
+        assert_eq!(
+            format!(
+                "{}",
+                Expr::Unary(UnaryOp::Not, Box::new(Expr::Identifier("flag".to_owned())))
+            ),
+            "!{flag}",
+        );
+        assert_eq!(
+            format!(
+                "{}",
+                Expr::Unary(
+                    UnaryOp::Neg,
+                    Box::new(Expr::Op(
+                        Box::new(Expr::Value(Value::from(1.0))),
+                        Op::Additive(AdditiveOp::Add),
+                        Box::new(Expr::Identifier("a".to_owned()))
+                    ))
+                )
+            ),
+            "-(1 + {a})",
+        );
+    }
+
+    #[test]
+    fn test_introspection() {
+        let expr = crate::parser::parser::parse_expr_from_str(
+            "if {user.tags[0]} == 'vip' then max({a}, len({user.name})) else -{b} + {a}",
+        )
+        .unwrap();
+        assert_eq!(expr.identifiers(), BTreeSet::from(["a", "b", "user"]));
+        assert_eq!(expr.functions(), BTreeSet::from(["len", "max"]));
+        assert_eq!(
+            expr.paths(),
+            BTreeSet::from(["user.name".to_owned(), "user.tags[0]".to_owned()])
+        );
+
+        let expr = Expr::Value(Value::from(1));
+        assert!(expr.identifiers().is_empty());
+        assert!(expr.functions().is_empty());
+        assert!(expr.paths().is_empty());
+    }
+
+    #[test]
+    fn test_node_path() {
+        let expr =
+            crate::parser::parser::parse_expr_from_str("if {a} then max(1, -{b}) else {c} + 1")
+                .unwrap();
+        let path = NodePath(vec![NodeStep::Then, NodeStep::Arg(1), NodeStep::Rhs]);
+        assert_eq!(path.to_string(), "/then/args/1/rhs");
+        assert_eq!(expr.node(&path), Some(&Expr::Identifier("b".to_owned())));
+        assert_eq!(
+            expr.node(&NodePath(vec![NodeStep::Else, NodeStep::Lhs])),
+            Some(&Expr::Identifier("c".to_owned()))
+        );
+        assert_eq!(expr.node(&NodePath(vec![NodeStep::Lhs])), None);
+        assert_eq!(NodePath::default().to_string(), "/");
+        assert_eq!(expr.node(&NodePath::default()), Some(expr.as_ref()));
+    }
+
+    #[test]
+    fn test_display_deep_expr() {
+        let expr = crate::parser::parser::parse_expr_from_str(&chain("{a}", PASS_DEPTH)).unwrap();
+        assert_eq!(
+            expr.to_string(),
+            format!(
+                "{}{{a}}{}",
+                "(".repeat(PASS_DEPTH - 1),
+                " || {a})".repeat(PASS_DEPTH - 1)
+            )
+        );
+
+        let mut expr = Expr::Identifier("a".to_owned());
+        for _ in 0..PASS_DEPTH / 2 {
+            expr = Expr::Conditional(
+                Box::new(Expr::Unary(UnaryOp::Not, Box::new(expr))),
+                Box::new(Expr::Value(Value::Null)),
+                Box::new(Expr::Call(
+                    "max".to_owned(),
+                    vec![
+                        Expr::Identifier("b".to_owned()),
+                        Expr::Value(Value::from(1)),
+                    ],
+                )),
+            );
+        }
+        assert_eq!(
+            expr.to_string(),
+            format!(
+                "{}{{a}}{}",
+                "(if !".repeat(PASS_DEPTH / 2),
+                " then null else max({b}, 1))".repeat(PASS_DEPTH / 2)
+            )
+        );
+    }
+
+    #[test]
+    fn test_drop_deep_expr() {
+        let mut expr = Expr::Identifier("a".to_owned());
+        for i in 0..EVALUATE_DEPTH {
+            expr = match i % 4 {
+                0 => Expr::Unary(UnaryOp::Not, Box::new(expr)),
+                1 => Expr::Op(
+                    Box::new(expr),
+                    Op::Additive(AdditiveOp::Add),
+                    Box::new(Expr::Value(Value::from(1))),
+                ),
+                2 => Expr::Call("abs".to_owned(), vec![expr]),
+                _ => Expr::Conditional(
+                    Box::new(Expr::Value(Value::from(true))),
+                    Box::new(Expr::Value(Value::Null)),
+                    Box::new(expr),
+                ),
+            };
+        }
+        drop(expr);
     }
 }
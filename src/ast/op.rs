@@ -35,6 +35,7 @@ pub enum RelationalOp {
     Eq,
     Neq,
     In,
+    Matches,
 }
 
 #[derive(Copy, Serialize, Deserialize, Debug, Clone, PartialEq, PartialOrd)]
@@ -45,6 +46,13 @@ pub enum MultiplicativeOp {
     Mod,
 }
 
+#[derive(Copy, Serialize, Deserialize, Debug, Clone, PartialEq, PartialOrd)]
+#[serde(untagged)]
+pub enum UnaryOp {
+    Not,
+    Neg,
+}
+
 impl Display for Op {
     fn fmt(&self, fmt: &mut Formatter) -> Result<(), Error> {
         use self::Op::*;
@@ -99,6 +107,17 @@ impl Display for RelationalOp {
             Eq => write!(fmt, "=="),
             Neq => write!(fmt, "!="),
             In => write!(fmt, "in"),
+            Matches => write!(fmt, "matches"),
+        }
+    }
+}
+
+impl Display for UnaryOp {
+    fn fmt(&self, fmt: &mut Formatter) -> Result<(), Error> {
+        use self::UnaryOp::*;
+        match *self {
+            Not => write!(fmt, "!"),
+            Neg => write!(fmt, "-"),
         }
     }
 }
@@ -155,11 +174,18 @@ mod tests {
         assert_eq!(format!("{}", Op::Relational(RelationalOp::Eq)), "==");
         assert_eq!(format!("{}", Op::Relational(RelationalOp::Neq)), "!=");
         assert_eq!(format!("{}", Op::Relational(RelationalOp::In)), "in");
+        assert_eq!(
+            format!("{}", Op::Relational(RelationalOp::Matches)),
+            "matches"
+        );
 
         assert_eq!(format!("{}", Op::Relational(RelationalOp::Lt)), "<");
         assert_eq!(format!("{}", Op::Relational(RelationalOp::Lte)), "<=");
         assert_eq!(format!("{}", Op::Relational(RelationalOp::Gt)), ">");
         assert_eq!(format!("{}", Op::Relational(RelationalOp::Gte)), ">=");
+
+        assert_eq!(format!("{}", UnaryOp::Not), "!");
+        assert_eq!(format!("{}", UnaryOp::Neg), "-");
     }
 
     #[test]
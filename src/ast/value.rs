@@ -1,21 +1,42 @@
+use serde::ser::{Serialize, SerializeMap, SerializeSeq, Serializer};
 use serde_json::Value as JsonValue;
+use std::collections::BTreeMap;
 use std::fmt::{Display, Error, Formatter};
 
+use crate::evaluator::evaluator::EvaluatorError;
+
 /// Value used by by the parser and evaluator.
 #[derive(Debug, Clone, PartialEq, PartialOrd)]
 pub enum Value {
+    Null,
     Bool(bool),
     Number(f64),
     String(String),
     Array(Vec<Value>),
+    Object(BTreeMap<String, Value>),
 }
 
 impl Display for Value {
     fn fmt(&self, fmt: &mut Formatter) -> Result<(), Error> {
         use self::Value::*;
-        match &*self {
+        match self {
+            Null => write!(fmt, "null"),
             Bool(n) => write!(fmt, "{}", n),
-            String(n) => write!(fmt, "{}", &n),
+            String(n) => {
+                write!(fmt, "'")?;
+                for c in n.chars() {
+                    match c {
+                        '\'' => write!(fmt, "\\'")?,
+                        '\\' => write!(fmt, "\\\\")?,
+                        '\n' => write!(fmt, "\\n")?,
+                        '\r' => write!(fmt, "\\r")?,
+                        '\t' => write!(fmt, "\\t")?,
+                        c if c.is_control() => write!(fmt, "\\u{{{:x}}}", c as u32)?,
+                        c => write!(fmt, "{}", c)?,
+                    }
+                }
+                write!(fmt, "'")
+            }
             Number(n) => write!(fmt, "{}", n),
             Array(array) => {
                 write!(fmt, "[")?;
@@ -30,6 +51,95 @@ impl Display for Value {
                 }
                 write!(fmt, "]")
             }
+            Object(object) => {
+                write!(fmt, "{{")?;
+                let mut once = false;
+                for (k, v) in object {
+                    if once {
+                        write!(fmt, ", ")?;
+                    } else {
+                        once = true;
+                    }
+                    write!(fmt, "{}: ", k)?;
+                    v.fmt(fmt)?;
+                }
+                write!(fmt, "}}")
+            }
+        }
+    }
+}
+
+/// Serialize as plain JSON; integral numbers are written without a fraction.
+impl Serialize for Value {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            Value::Null => serializer.serialize_unit(),
+            Value::Bool(v) => serializer.serialize_bool(*v),
+            Value::Number(v) if v.fract() == 0.0 && v.abs() < 9007199254740992.0 => {
+                serializer.serialize_i64(*v as i64)
+            }
+            Value::Number(v) => serializer.serialize_f64(*v),
+            Value::String(v) => serializer.serialize_str(v),
+            Value::Array(array) => {
+                let mut seq = serializer.serialize_seq(Some(array.len()))?;
+                for v in array {
+                    seq.serialize_element(v)?;
+                }
+                seq.end()
+            }
+            Value::Object(object) => {
+                let mut map = serializer.serialize_map(Some(object.len()))?;
+                for (k, v) in object {
+                    map.serialize_entry(k, v)?;
+                }
+                map.end()
+            }
+        }
+    }
+}
+
+/// Type tag of a `Value`; `Any` matches every value.
+#[derive(Copy, Debug, Clone, PartialEq, Eq, Hash)]
+pub enum ValueType {
+    Any,
+    Null,
+    Bool,
+    Number,
+    String,
+    Array,
+    Object,
+}
+
+impl ValueType {
+    pub fn matches(&self, value: &Value) -> bool {
+        *self == ValueType::Any || *self == value.value_type()
+    }
+}
+
+impl Display for ValueType {
+    fn fmt(&self, fmt: &mut Formatter) -> Result<(), Error> {
+        use self::ValueType::*;
+        match self {
+            Any => write!(fmt, "any"),
+            Null => write!(fmt, "null"),
+            Bool => write!(fmt, "bool"),
+            Number => write!(fmt, "number"),
+            String => write!(fmt, "string"),
+            Array => write!(fmt, "array"),
+            Object => write!(fmt, "object"),
+        }
+    }
+}
+
+impl Value {
+    pub fn value_type(&self) -> ValueType {
+        match self {
+            Value::Null => ValueType::Null,
+            Value::Bool(_) => ValueType::Bool,
+            Value::Number(_) => ValueType::Number,
+            Value::String(_) => ValueType::String,
+            Value::Array(_) => ValueType::Array,
+            Value::Object(_) => ValueType::Object,
         }
     }
 }
@@ -133,6 +243,7 @@ impl From<bool> for Value {
 impl From<&JsonValue> for Value {
     fn from(jv: &JsonValue) -> Self {
         match jv {
+            JsonValue::Null => Value::Null,
             JsonValue::String(v) => Value::String(v.clone()),
             JsonValue::Number(v) => Value::Number(v.clone().as_f64().unwrap()),
             JsonValue::Bool(v) => Value::Bool(*v),
@@ -144,7 +255,101 @@ impl From<&JsonValue> for Value {
 
                 Value::Array(rs)
             }
-            v => panic!("unsupport type {}", v),
+            JsonValue::Object(object) => {
+                let mut rs = BTreeMap::new();
+                for (k, v) in object {
+                    rs.insert(k.clone(), Value::from(v));
+                }
+
+                Value::Object(rs)
+            }
+        }
+    }
+}
+
+fn unexpected_type(expected: &str, value: Value) -> EvaluatorError {
+    EvaluatorError::UnexpectedType(expected.to_owned(), value)
+}
+
+/// Convert `Value` to boolean
+///
+/// # Examples
+///
+/// ```
+/// use evaluator_rs::Value;
+///
+/// let b: bool = Value::from(true).try_into().unwrap();
+/// ```
+impl TryFrom<Value> for bool {
+    type Error = EvaluatorError;
+
+    fn try_from(value: Value) -> Result<Self, Self::Error> {
+        match value {
+            Value::Bool(v) => Ok(v),
+            v => Err(unexpected_type("bool", v)),
+        }
+    }
+}
+
+/// Convert `Value` to 64-bit float
+impl TryFrom<Value> for f64 {
+    type Error = EvaluatorError;
+
+    fn try_from(value: Value) -> Result<Self, Self::Error> {
+        match value {
+            Value::Number(v) => Ok(v),
+            v => Err(unexpected_type("number", v)),
+        }
+    }
+}
+
+/// Convert `Value` to 32-bit float
+impl TryFrom<Value> for f32 {
+    type Error = EvaluatorError;
+
+    fn try_from(value: Value) -> Result<Self, Self::Error> {
+        f64::try_from(value).map(|v| v as f32)
+    }
+}
+
+/// Convert `Value` to 64-bit integer; the number must be integral and in range
+impl TryFrom<Value> for i64 {
+    type Error = EvaluatorError;
+
+    fn try_from(value: Value) -> Result<Self, Self::Error> {
+        match value {
+            Value::Number(v) if v.fract() == 0.0 && v >= i64::MIN as f64 && v < i64::MAX as f64 => {
+                Ok(v as i64)
+            }
+            v => Err(unexpected_type("integer", v)),
+        }
+    }
+}
+
+/// Convert `Value` to 32-bit integer; the number must be integral and in range
+impl TryFrom<Value> for i32 {
+    type Error = EvaluatorError;
+
+    fn try_from(value: Value) -> Result<Self, Self::Error> {
+        match value {
+            Value::Number(v)
+                if v.fract() == 0.0 && v >= i32::MIN as f64 && v <= i32::MAX as f64 =>
+            {
+                Ok(v as i32)
+            }
+            v => Err(unexpected_type("integer", v)),
+        }
+    }
+}
+
+/// Convert `Value` to string
+impl TryFrom<Value> for String {
+    type Error = EvaluatorError;
+
+    fn try_from(value: Value) -> Result<Self, Self::Error> {
+        match value {
+            Value::String(v) => Ok(v),
+            v => Err(unexpected_type("string", v)),
         }
     }
 }
@@ -160,6 +365,18 @@ mod tests {
         assert_eq!(Value::Number(1.5), Value::from(1.5_f32));
         assert_eq!(Value::Number(1.5), Value::from(1.5_f64));
         assert_eq!(Value::Bool(true), Value::from(true));
+        assert_eq!(Value::Null, Value::from(&JsonValue::Null));
+        assert_eq!(
+            Value::Array(vec![Value::Null, Value::from(1)]),
+            Value::from(&serde_json::json!([null, 1])),
+        );
+        assert_eq!(
+            Value::Object(BTreeMap::from([
+                ("city".to_owned(), Value::from("Hanoi")),
+                ("zip".to_owned(), Value::Null),
+            ])),
+            Value::from(&serde_json::json!({"city": "Hanoi", "zip": null})),
+        );
 
         assert_eq!(
             Value::String("hello world".to_owned()),
@@ -167,14 +384,82 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_try_from_value() {
+        assert_eq!(bool::try_from(Value::from(true)), Ok(true));
+        assert_eq!(f64::try_from(Value::from(1.5)), Ok(1.5));
+        assert_eq!(f32::try_from(Value::from(1.5)), Ok(1.5));
+        assert_eq!(i64::try_from(Value::from(-3)), Ok(-3));
+        assert_eq!(i32::try_from(Value::from(7)), Ok(7));
+        assert_eq!(String::try_from(Value::from("a")), Ok("a".to_owned()));
+
+        assert_eq!(
+            bool::try_from(Value::from(1)),
+            Err(EvaluatorError::UnexpectedType(
+                "bool".to_owned(),
+                Value::from(1)
+            ))
+        );
+        assert_eq!(
+            i64::try_from(Value::from(1.5)),
+            Err(EvaluatorError::UnexpectedType(
+                "integer".to_owned(),
+                Value::from(1.5)
+            ))
+        );
+        assert_eq!(
+            i32::try_from(Value::from(1e10)),
+            Err(EvaluatorError::UnexpectedType(
+                "integer".to_owned(),
+                Value::from(1e10)
+            ))
+        );
+        assert_eq!(
+            String::try_from(Value::Null),
+            Err(EvaluatorError::UnexpectedType(
+                "string".to_owned(),
+                Value::Null
+            ))
+        );
+    }
+
+    #[test]
+    fn test_serialize() {
+        let value = Value::from(&serde_json::json!({
+            "a": [1, 1.5, -2, null],
+            "b": { "c": "text", "d": true },
+        }));
+        assert_eq!(
+            serde_json::to_string(&value).unwrap(),
+            r#"{"a":[1,1.5,-2,null],"b":{"c":"text","d":true}}"#
+        );
+    }
+
+    #[test]
+    fn test_value_type() {
+        assert_eq!(Value::Null.value_type(), ValueType::Null);
+        assert_eq!(Value::from(1).value_type(), ValueType::Number);
+        assert_eq!(Value::from("a").value_type(), ValueType::String);
+        assert_eq!(Value::Array(vec![]).value_type(), ValueType::Array);
+        assert!(ValueType::Any.matches(&Value::from(true)));
+        assert!(ValueType::Bool.matches(&Value::from(true)));
+        assert!(!ValueType::Bool.matches(&Value::from(1)));
+        assert_eq!(format!("{}", ValueType::Object), "object");
+    }
+
     #[test]
     fn test_display() {
         assert_eq!(format!("{}", Value::Number(1_f64)), "1");
         assert_eq!(format!("{}", Value::Number(1.1)), "1.1");
         assert_eq!(format!("{}", Value::Bool(true)), "true");
+        assert_eq!(format!("{}", Value::Null), "null");
         assert_eq!(
             format!("{}", Value::String("hello world".to_owned())),
-            "hello world"
+            "'hello world'"
+        );
+        assert_eq!(
+            format!("{}", Value::from("it's a\\b\n\u{7}")),
+            r"'it\'s a\\b\n\u{7}'"
         );
         assert_eq!(
             format!(
@@ -183,5 +468,15 @@ mod tests {
             ),
             "[1, 2, 3]"
         );
+        assert_eq!(
+            format!(
+                "{}",
+                Value::Object(BTreeMap::from([
+                    ("a".to_owned(), Value::from(1)),
+                    ("b".to_owned(), Value::Array(vec!(Value::from(true)))),
+                ]))
+            ),
+            "{a: 1, b: [true]}"
+        );
     }
 }
@@ -0,0 +1,683 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::{Display, Error, Formatter};
+use std::str::FromStr;
+use thiserror::Error;
+
+use crate::ast::{
+    expr::{Expr, NodePath, NodeStep, PathSegment},
+    op::{Op, UnaryOp},
+    value::{Value, ValueType},
+};
+use crate::evaluator::evaluator::{
+    evaluate_binary_expr, evaluate_unary_expr, Evaluator, EvaluatorError, Located,
+};
+use crate::evaluator::function::{builtin, Arity};
+
+/// Static type of an expression; `Any` is a value of unknown type.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Type {
+    Any,
+    Null,
+    Bool,
+    Number,
+    String,
+    Array(Box<Type>),
+    Object,
+}
+
+impl Type {
+    /// Type of a literal value
+    pub fn of(value: &Value) -> Type {
+        match value {
+            Value::Null => Type::Null,
+            Value::Bool(_) => Type::Bool,
+            Value::Number(_) => Type::Number,
+            Value::String(_) => Type::String,
+            Value::Array(items) => Type::Array(Box::new(
+                items
+                    .iter()
+                    .map(Type::of)
+                    .reduce(unify)
+                    .unwrap_or(Type::Any),
+            )),
+            Value::Object(_) => Type::Object,
+        }
+    }
+
+    /// Concrete types a value of this type may have
+    fn candidates(&self) -> Vec<Type> {
+        match self {
+            Type::Any => vec![
+                Type::Null,
+                Type::Bool,
+                Type::Number,
+                Type::String,
+                Type::Array(Box::new(Type::Any)),
+                Type::Object,
+            ],
+            t => vec![t.clone()],
+        }
+    }
+
+    /// Value of this type, used to probe the evaluator
+    fn representative(&self) -> Value {
+        match self {
+            Type::Any | Type::Null => Value::Null,
+            Type::Bool => Value::Bool(false),
+            Type::Number => Value::Number(1.0),
+            Type::String => Value::String(String::new()),
+            Type::Array(_) => Value::Array(vec![]),
+            Type::Object => Value::Object(BTreeMap::new()),
+        }
+    }
+
+    fn accepts(&self, expected: ValueType) -> bool {
+        *self == Type::Any || expected.matches(&self.representative())
+    }
+}
+
+/// Type of the values of a `ValueType`, arrays holding any type
+impl From<ValueType> for Type {
+    fn from(t: ValueType) -> Self {
+        match t {
+            ValueType::Any => Type::Any,
+            ValueType::Null => Type::Null,
+            ValueType::Bool => Type::Bool,
+            ValueType::Number => Type::Number,
+            ValueType::String => Type::String,
+            ValueType::Array => Type::Array(Box::new(Type::Any)),
+            ValueType::Object => Type::Object,
+        }
+    }
+}
+
+impl Display for Type {
+    fn fmt(&self, fmt: &mut Formatter) -> Result<(), Error> {
+        match self {
+            Type::Any => write!(fmt, "any"),
+            Type::Null => write!(fmt, "null"),
+            Type::Bool => write!(fmt, "bool"),
+            Type::Number => write!(fmt, "number"),
+            Type::String => write!(fmt, "string"),
+            Type::Array(item) => write!(fmt, "array<{}>", item),
+            Type::Object => write!(fmt, "object"),
+        }
+    }
+}
+
+/// Parse a type from its display form such as `array<number>`, returning the input if it is not
+/// a type
+impl FromStr for Type {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim() {
+            "any" => Ok(Type::Any),
+            "null" => Ok(Type::Null),
+            "bool" => Ok(Type::Bool),
+            "number" => Ok(Type::Number),
+            "string" => Ok(Type::String),
+            "object" => Ok(Type::Object),
+            t => match t.strip_prefix("array<").and_then(|t| t.strip_suffix('>')) {
+                Some(item) => Ok(Type::Array(Box::new(item.parse().map_err(|_| s)?))),
+                None => Err(s.to_owned()),
+            },
+        }
+    }
+}
+
+#[derive(Error, Debug, PartialEq)]
+pub enum TypeError {
+    #[error("unknown identifier {0}")]
+    UnknownIdentifier(String),
+    #[error("invalid path {0}: cannot resolve {1} on {2}")]
+    InvalidPath(String, PathSegment, Type),
+    #[error("invalid operation {0} {1} {2}")]
+    InvalidOperation(Type, Op, Type),
+    #[error("invalid operation {0}{1}")]
+    InvalidUnaryOperation(UnaryOp, Type),
+    #[error("invalid condition {0}")]
+    InvalidCondition(Type),
+    #[error("unknown function {0}")]
+    UnknownFunction(String),
+    #[error("function {0} expects {1} arguments, got {2}")]
+    InvalidArgumentCount(String, Arity, usize),
+    #[error("invalid argument {1} for function {0}")]
+    InvalidArgument(String, Type),
+}
+
+impl Evaluator {
+    /// Check `expr` against the parameter types in `schema` without evaluating it.
+    ///
+    /// Returns the type of the expression, or every error found, located at the subexpression it
+    /// applies to. Operators accept exactly the
+    /// operand types the evaluator accepts; function arguments are checked against the
+    /// parameter types of the function. Built-in functions result in their declared type, host
+    /// functions in `Type::Any`.
+    pub fn type_check(
+        &self,
+        expr: &Expr,
+        schema: &HashMap<&str, Type>,
+    ) -> Result<Type, Vec<Located<TypeError>>> {
+        let mut checker = Checker {
+            evaluator: self,
+            schema,
+            path: vec![],
+            errors: vec![],
+        };
+        let t = checker.check(expr);
+
+        match checker.errors.is_empty() {
+            true => Ok(t),
+            false => Err(checker.errors),
+        }
+    }
+}
+
+/// Check expression against parameter types with the built-in functions
+///
+/// Examples:
+///
+/// ```
+/// use evaluator_rs::*;
+/// use std::collections::HashMap;
+///
+/// let schema = HashMap::from([("age", Type::Number), ("tags", Type::Array(Box::new(Type::String)))]);
+///
+/// let expr = parse_expr_from_str("{age} >= 18 && 'vip' in {tags}").unwrap();
+/// assert_eq!(type_check(&expr, &schema), Ok(Type::Bool));
+///
+/// let expr = parse_expr_from_str("{age} + 'x' > {name}").unwrap();
+/// assert_eq!(type_check(&expr, &schema).unwrap_err().len(), 2);
+/// ```
+pub fn type_check(
+    expr: &Expr,
+    schema: &HashMap<&str, Type>,
+) -> Result<Type, Vec<Located<TypeError>>> {
+    Evaluator::new().type_check(expr, schema)
+}
+
+struct Checker<'a> {
+    evaluator: &'a Evaluator,
+    schema: &'a HashMap<&'a str, Type>,
+    /// Path of the subexpression being checked
+    path: Vec<NodeStep>,
+    errors: Vec<Located<TypeError>>,
+}
+
+/// Unit of work of the non-recursive checker
+enum Task<'e> {
+    /// Check an operand, entering it by its step from the parent
+    Check(&'e Expr, Option<NodeStep>),
+    /// Leave an operand once checked
+    Leave,
+    /// Combine the types of the two topmost operands
+    Op(Op),
+    Unary(UnaryOp),
+    /// Check the type of the condition of a conditional
+    Condition,
+    /// Unify the types of the two branches of a conditional
+    Branches,
+    /// Check a call with the topmost types as argument types
+    Call(&'e str, usize),
+}
+
+impl Checker<'_> {
+    /// Type of `expr`; subexpressions with errors are typed as `Any` so errors do not cascade
+    fn check(&mut self, expr: &Expr) -> Type {
+        let mut tasks = vec![Task::Check(expr, None)];
+        let mut types = vec![];
+
+        while let Some(task) = tasks.pop() {
+            let t = match task {
+                Task::Check(expr, step) => {
+                    if let Some(step) = step {
+                        self.path.push(step);
+                        tasks.push(Task::Leave);
+                    }
+                    match expr {
+                        Expr::Value(v) => Type::of(v),
+                        Expr::Identifier(name) => self.identifier(name),
+                        Expr::Path(name, segments) => self.path(name, segments),
+                        Expr::Op(lhs, op, rhs) => {
+                            tasks.extend([
+                                Task::Op(*op),
+                                Task::Check(rhs, Some(NodeStep::Rhs)),
+                                Task::Check(lhs, Some(NodeStep::Lhs)),
+                            ]);
+                            continue;
+                        }
+                        Expr::Unary(op, rhs) => {
+                            tasks.extend([Task::Unary(*op), Task::Check(rhs, Some(NodeStep::Rhs))]);
+                            continue;
+                        }
+                        Expr::Call(name, args) => {
+                            tasks.push(Task::Call(name, args.len()));
+                            tasks.extend(
+                                args.iter()
+                                    .enumerate()
+                                    .rev()
+                                    .map(|(i, arg)| Task::Check(arg, Some(NodeStep::Arg(i)))),
+                            );
+                            continue;
+                        }
+                        Expr::Conditional(cond, then, otherwise) => {
+                            tasks.extend([
+                                Task::Branches,
+                                Task::Check(otherwise, Some(NodeStep::Else)),
+                                Task::Check(then, Some(NodeStep::Then)),
+                                Task::Condition,
+                                Task::Check(cond, Some(NodeStep::If)),
+                            ]);
+                            continue;
+                        }
+                    }
+                }
+                Task::Leave => {
+                    self.path.pop();
+                    continue;
+                }
+                Task::Op(op) => {
+                    let r = types.pop().expect("operand checked");
+                    let l = types.pop().expect("operand checked");
+                    self.op(l, op, r)
+                }
+                Task::Unary(op) => {
+                    let r = types.pop().expect("operand checked");
+                    self.unary(op, r)
+                }
+                Task::Condition => {
+                    match types.pop().expect("condition checked") {
+                        Type::Bool | Type::Any => {}
+                        t => self.error(TypeError::InvalidCondition(t)),
+                    }
+                    continue;
+                }
+                Task::Branches => {
+                    let otherwise = types.pop().expect("branch checked");
+                    let then = types.pop().expect("branch checked");
+                    unify(then, otherwise)
+                }
+                Task::Call(name, count) => {
+                    let args = types.split_off(types.len() - count);
+                    self.call(name, &args)
+                }
+            };
+            types.push(t);
+        }
+
+        types.pop().expect("expression checked")
+    }
+
+    fn identifier(&mut self, name: &str) -> Type {
+        match self.schema.get(name) {
+            Some(t) => t.clone(),
+            None => {
+                self.error(TypeError::UnknownIdentifier(name.to_owned()));
+                Type::Any
+            }
+        }
+    }
+
+    fn path(&mut self, name: &str, segments: &[PathSegment]) -> Type {
+        let mut current = self.identifier(name);
+
+        for segment in segments {
+            current = match (current, segment) {
+                (Type::Any, _) | (Type::Object, PathSegment::Field(_)) => Type::Any,
+                (Type::Array(item), PathSegment::Index(_)) => *item,
+                (t, segment) => {
+                    let path = segments.iter().fold(name.to_string(), |mut path, s| {
+                        path.push_str(&s.to_string());
+                        path
+                    });
+                    self.error(TypeError::InvalidPath(path, segment.clone(), t));
+                    return Type::Any;
+                }
+            };
+        }
+
+        current
+    }
+
+    fn op(&mut self, l: Type, op: Op, r: Type) -> Type {
+        let mut results = None;
+        for lc in l.candidates() {
+            for rc in r.candidates() {
+                let (lv, rv) = (lc.representative(), rc.representative());
+                let result = evaluate_binary_expr(&lv, &op, &rv, self.evaluator.regexes());
+                if let Ok(v) = result {
+                    results = Some(match results {
+                        None => Type::of(&v),
+                        Some(t) => unify(t, Type::of(&v)),
+                    });
+                }
+            }
+        }
+
+        self.result(results, || {
+            TypeError::InvalidOperation(l.clone(), op, r.clone())
+        })
+    }
+
+    fn unary(&mut self, op: UnaryOp, r: Type) -> Type {
+        let results = r
+            .candidates()
+            .iter()
+            .filter_map(|c| evaluate_unary_expr(&op, &c.representative()).ok())
+            .map(|v| Type::of(&v))
+            .reduce(unify);
+        self.result(results, || TypeError::InvalidUnaryOperation(op, r.clone()))
+    }
+
+    fn call(&mut self, name: &str, types: &[Type]) -> Type {
+        let (arity, returns) = match (self.evaluator.function(name), builtin(name)) {
+            (Some(function), _) => {
+                let signature = &function.signature;
+                for (i, t) in types.iter().enumerate() {
+                    if !signature.param(i).map_or(true, |p| t.accepts(p)) {
+                        self.error(TypeError::InvalidArgument(name.to_owned(), t.clone()));
+                    }
+                }
+                (signature.arity(), Type::Any)
+            }
+            (None, Some(builtin)) => {
+                for (i, t) in types.iter().enumerate() {
+                    if !builtin.param(i).iter().any(|p| t.accepts(*p)) {
+                        self.error(TypeError::InvalidArgument(name.to_owned(), t.clone()));
+                    }
+                }
+                (builtin.arity, Type::from(builtin.returns))
+            }
+            (None, None) => {
+                self.error(TypeError::UnknownFunction(name.to_owned()));
+                return Type::Any;
+            }
+        };
+
+        if !arity.accepts(types.len()) {
+            self.error(TypeError::InvalidArgumentCount(
+                name.to_owned(),
+                arity,
+                types.len(),
+            ));
+        }
+
+        returns
+    }
+
+    fn error(&mut self, error: TypeError) {
+        self.errors.push(Located {
+            error,
+            path: NodePath(self.path.clone()),
+        });
+    }
+
+    fn result<F>(&mut self, result: Option<Type>, error: F) -> Type
+    where
+        F: FnOnce() -> TypeError,
+    {
+        match result {
+            Some(t) => t,
+            None => {
+                self.error(error());
+                Type::Any
+            }
+        }
+    }
+}
+
+/// Common type of two values, `Any` if they differ
+fn unify(a: Type, b: Type) -> Type {
+    match (a, b) {
+        (Type::Array(a), Type::Array(b)) => Type::Array(Box::new(unify(*a, *b))),
+        (a, b) if a == b => a,
+        _ => Type::Any,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::op::{AdditiveOp, LogicalOp, RelationalOp};
+    use crate::evaluator::function::{FunctionError, Signature};
+    use crate::parser::parser;
+    use crate::test_util::{chain, deep_exprs, PASS_DEPTH};
+
+    struct TestCase {
+        expr: &'static str,
+        want: Result<Type, Vec<TypeError>>,
+    }
+
+    fn array(item: Type) -> Type {
+        Type::Array(Box::new(item))
+    }
+
+    #[test]
+    fn test_type_check() {
+        let schema = HashMap::from([
+            ("age", Type::Number),
+            ("name", Type::String),
+            ("vip", Type::Bool),
+            ("tags", array(Type::String)),
+            ("matrix", array(array(Type::Number))),
+            ("user", Type::Object),
+            ("extra", Type::Any),
+        ]);
+        let test_cases: Vec<TestCase> = vec![
+            TestCase {
+                expr: "{age} >= 18 && !{vip}",
+                want: Ok(Type::Bool),
+            },
+            TestCase {
+                expr: "-{age} * 2 + 1",
+                want: Ok(Type::Number),
+            },
+            TestCase {
+                expr: "{name} matches '^a' || {name} in {tags}",
+                want: Ok(Type::Bool),
+            },
+            TestCase {
+                expr: "{matrix[0][1]} + {tags[0]}",
+                want: Err(vec![TypeError::InvalidOperation(
+                    Type::Number,
+                    Op::Additive(AdditiveOp::Add),
+                    Type::String,
+                )]),
+            },
+            TestCase {
+                expr: "{user.address.city}",
+                want: Ok(Type::Any),
+            },
+            TestCase {
+                expr: "{extra} + 1",
+                want: Ok(Type::Number),
+            },
+            TestCase {
+                expr: "{extra} == {age}",
+                want: Ok(Type::Bool),
+            },
+            TestCase {
+                expr: "{age} == null",
+                want: Ok(Type::Bool),
+            },
+            TestCase {
+                expr: "[1, 2] == {tags}",
+                want: Ok(Type::Bool),
+            },
+            TestCase {
+                expr: "{tags} in [1]",
+                want: Err(vec![TypeError::InvalidOperation(
+                    array(Type::String),
+                    Op::Relational(RelationalOp::In),
+                    array(Type::Number),
+                )]),
+            },
+            TestCase {
+                expr: "if {vip} then {age} else 0",
+                want: Ok(Type::Number),
+            },
+            TestCase {
+                expr: "if {age} then {age} else 'none'",
+                want: Err(vec![TypeError::InvalidCondition(Type::Number)]),
+            },
+            TestCase {
+                expr: "{age} + 'x' > {missing} && {vip} || !{name}",
+                want: Err(vec![
+                    TypeError::InvalidOperation(
+                        Type::Number,
+                        Op::Additive(AdditiveOp::Add),
+                        Type::String,
+                    ),
+                    TypeError::UnknownIdentifier("missing".to_owned()),
+                    TypeError::InvalidUnaryOperation(UnaryOp::Not, Type::String),
+                ]),
+            },
+            TestCase {
+                expr: "{vip} && {age}",
+                want: Err(vec![TypeError::InvalidOperation(
+                    Type::Bool,
+                    Op::Logical(LogicalOp::And),
+                    Type::Number,
+                )]),
+            },
+            TestCase {
+                expr: "{tags.first} == {age[0]}",
+                want: Err(vec![
+                    TypeError::InvalidPath(
+                        "tags.first".to_owned(),
+                        PathSegment::Field("first".to_owned()),
+                        array(Type::String),
+                    ),
+                    TypeError::InvalidPath(
+                        "age[0]".to_owned(),
+                        PathSegment::Index(0),
+                        Type::Number,
+                    ),
+                ]),
+            },
+            TestCase {
+                expr: "len({tags}) > 1 && nope({age}) && upper() == ''",
+                want: Err(vec![
+                    TypeError::UnknownFunction("nope".to_owned()),
+                    TypeError::InvalidArgumentCount("upper".to_owned(), Arity::Exact(1), 0),
+                ]),
+            },
+            TestCase {
+                expr: "len({age}) > 1 || starts_with({name}, {age}) || contains({tags}, {age})",
+                want: Err(vec![
+                    TypeError::InvalidArgument("len".to_owned(), Type::Number),
+                    TypeError::InvalidArgument("starts_with".to_owned(), Type::Number),
+                ]),
+            },
+            TestCase {
+                expr: "max({age}, {matrix[0]}, {extra}) + round(abs({age}), 2)",
+                want: Ok(Type::Number),
+            },
+            TestCase {
+                expr: "upper({name}) + 1",
+                want: Err(vec![TypeError::InvalidOperation(
+                    Type::String,
+                    Op::Additive(AdditiveOp::Add),
+                    Type::Number,
+                )]),
+            },
+        ];
+        test_cases.iter().for_each(|case| {
+            let expr = parser::parse_expr_from_str(case.expr).unwrap();
+            let output = type_check(&expr, &schema)
+                .map_err(|errors| errors.into_iter().map(|e| e.error).collect::<Vec<_>>());
+            assert_eq!(case.want, output, "expr: {}", case.expr);
+        });
+    }
+
+    #[test]
+    fn test_type_check_error_path() {
+        let schema = HashMap::from([("age", Type::Number), ("name", Type::String)]);
+        let test_cases = vec![
+            ("{age} + 'x' > {missing}", vec!["/lhs", "/rhs"]),
+            ("if {age} then 1 else 2", vec!["/"]),
+            (
+                "{age} > 1 && max({age}, -{name}) > 1",
+                vec!["/rhs/lhs/args/1"],
+            ),
+            ("{age} > 1 && {age.x}", vec!["/rhs"]),
+        ];
+        for (expr, paths) in test_cases {
+            let expr = parser::parse_expr_from_str(expr).unwrap();
+            let errors = type_check(&expr, &schema).unwrap_err();
+            let errors: Vec<String> = errors.iter().map(|e| e.path.to_string()).collect();
+            assert_eq!(errors, paths, "expr: {}", expr);
+        }
+    }
+
+    #[test]
+    fn test_type_check_custom_function() {
+        let evaluator = Evaluator::new().with_function(
+            "score",
+            Signature::new(vec![ValueType::String, ValueType::Number]),
+            |_| Ok::<_, FunctionError>(Value::from(1)),
+        );
+        let schema = HashMap::from([("id", Type::String), ("weight", Type::Number)]);
+
+        let expr = parser::parse_expr_from_str("score({id}, {weight}) > 0.5").unwrap();
+        assert_eq!(evaluator.type_check(&expr, &schema), Ok(Type::Bool));
+
+        let expr = parser::parse_expr_from_str("score({weight}, {id})").unwrap();
+        assert_eq!(
+            evaluator.type_check(&expr, &schema),
+            Err(vec![
+                Located {
+                    error: TypeError::InvalidArgument("score".to_owned(), Type::Number),
+                    path: NodePath::default(),
+                },
+                Located {
+                    error: TypeError::InvalidArgument("score".to_owned(), Type::String),
+                    path: NodePath::default(),
+                },
+            ])
+        );
+    }
+
+    #[test]
+    fn test_type_check_deep_expr() {
+        let schema = HashMap::from([("a", Type::Bool), ("n", Type::Number), ("s", Type::String)]);
+        for (s, value) in deep_exprs(PASS_DEPTH) {
+            let expr = parser::parse_expr_from_str(&s).unwrap();
+            assert_eq!(type_check(&expr, &schema), Ok(Type::of(&value)));
+        }
+
+        let expr = parser::parse_expr_from_str(&(chain("{a}", PASS_DEPTH) + " || {n}")).unwrap();
+        assert_eq!(
+            type_check(&expr, &schema),
+            Err(vec![Located {
+                error: TypeError::InvalidOperation(
+                    Type::Bool,
+                    Op::Logical(LogicalOp::Or),
+                    Type::Number,
+                ),
+                path: NodePath::default(),
+            }])
+        );
+    }
+
+    #[test]
+    fn test_type_display() {
+        assert_eq!(
+            array(array(Type::Number)).to_string(),
+            "array<array<number>>"
+        );
+        assert_eq!(
+            Type::of(&Value::from(&serde_json::json!([1, "a"]))),
+            array(Type::Any)
+        );
+
+        assert_eq!(
+            "array<array<number>>".parse(),
+            Ok(array(array(Type::Number)))
+        );
+        assert_eq!(" bool ".parse(), Ok(Type::Bool));
+        assert_eq!("array<int>".parse::<Type>(), Err("array<int>".to_owned()));
+        assert_eq!("array<".parse::<Type>(), Err("array<".to_owned()));
+    }
+}
@@ -0,0 +1,723 @@
+use regex::Regex;
+use std::borrow::Cow;
+use std::fmt::{self, Debug, Formatter};
+use std::sync::Arc;
+
+use crate::ast::{
+    expr::{Expr, NodePath, NodeStep, PathSegment},
+    op::{LogicalOp, Op, RelationalOp, UnaryOp},
+    value::Value,
+};
+use crate::evaluator::context::Context;
+use crate::evaluator::evaluator::{
+    call_function, evaluate_binary_expr, evaluate_unary_expr, resolve_path, short_circuit,
+    with_default_evaluator, Callee, Evaluator, EvaluatorError, LocatedError,
+};
+use crate::evaluator::function::{Builtin, Function};
+use crate::evaluator::limits::{Budget, Limits};
+use crate::evaluator::regex_cache::RegexCache;
+
+/// Expression compiled into a flat list of instructions, with identifiers resolved to slot
+/// indices.
+///
+/// Compile once and evaluate many times against a slice holding the value of each slot.
+/// Neither compiling nor evaluating recurses, so expressions of any depth can be compiled.
+/// Evaluation is bounded by the limits of the evaluator that compiled the expression, and
+/// errors are located at the subexpression that fails as with `Evaluator::evaluate`.
+///
+/// # Examples
+///
+/// ```
+/// use evaluator_rs::*;
+///
+/// let expr = parse_expr_from_str("{price} * {quantity} > 100").unwrap();
+/// let compiled = compile(&expr);
+/// assert_eq!(compiled.slots(), ["price", "quantity"]);
+///
+/// let rs = compiled.evaluate(&[Value::from(30), Value::from(4)]).unwrap();
+/// assert_eq!(rs, Value::from(true));
+/// ```
+pub struct CompiledExpr {
+    slots: Vec<String>,
+    /// Instructions along with the depth and node of the subexpression they evaluate
+    code: Vec<(Instruction, usize, usize)>,
+    /// Parent and step of every node but the root, node `i + 1` at index `i`; the root is
+    /// node 0
+    nodes: Vec<(usize, NodeStep)>,
+    limits: Limits,
+    regexes: Arc<RegexCache>,
+}
+
+/// Instruction of a compiled expression, working on a stack of values
+enum Instruction {
+    Value(Value),
+    /// Push the value of a slot, named for errors
+    Load(usize, String),
+    /// Push the value at a path into the value of a slot
+    Path(usize, String, Vec<PathSegment>),
+    /// Fail with an error found at compile time
+    Fail(EvaluatorError),
+    /// Replace the topmost value and jump to the given instruction if it decides the operation,
+    /// or go on with the right operand
+    ShortCircuit(LogicalOp, usize),
+    /// Combine the two topmost values
+    Binary(Op),
+    /// Combine the topmost value with a constant right operand
+    BinaryValue(Op, Value),
+    /// Combine a constant left operand with the topmost value
+    ValueBinary(Value, Op),
+    Unary(UnaryOp),
+    /// Match the topmost value against a constant pattern
+    Matches(Regex, Value),
+    /// Pop the condition and jump to the given instruction if it is false
+    Branch(usize),
+    Jump(usize),
+    /// Call a function with the topmost values as arguments
+    CallHost(String, Function, usize),
+    CallBuiltin(String, &'static Builtin, usize),
+}
+
+impl Instruction {
+    /// Account for the subexpressions evaluated by the instruction: its own, at `depth`, and
+    /// the constant operand it holds if any, failing with the step to that operand when it is
+    /// the one over the limits. The right operand of a logical operation is accounted for by
+    /// `ShortCircuit`.
+    fn charge(
+        &self,
+        budget: &mut Budget,
+        depth: usize,
+    ) -> Result<(), (EvaluatorError, Option<NodeStep>)> {
+        let operand = match self {
+            Instruction::Jump(_) | Instruction::Binary(Op::Logical(_)) => return Ok(()),
+            Instruction::BinaryValue(_, _) | Instruction::Matches(_, _) => NodeStep::Rhs,
+            Instruction::ValueBinary(_, _) => NodeStep::Lhs,
+            _ => return budget.step(depth).map_err(|e| (e, None)),
+        };
+        budget.step(depth).map_err(|e| (e, None))?;
+        budget.step(depth + 1).map_err(|e| (e, Some(operand)))
+    }
+}
+
+/// Operands held inline by a run before spilling to the heap
+const INLINE_OPERANDS: usize = 8;
+
+/// Operand stack of a run, borrowing constants and slot values. Held inline so that evaluating
+/// an expression needing few operands at once does not allocate.
+struct Stack<'a> {
+    inline: [Option<Cow<'a, Value>>; INLINE_OPERANDS],
+    spilled: Vec<Cow<'a, Value>>,
+    len: usize,
+}
+
+impl<'a> Stack<'a> {
+    fn new() -> Self {
+        Stack {
+            inline: Default::default(),
+            spilled: vec![],
+            len: 0,
+        }
+    }
+
+    fn push(&mut self, value: Cow<'a, Value>) {
+        match self.inline.get_mut(self.len) {
+            Some(slot) => *slot = Some(value),
+            None => self.spilled.push(value),
+        }
+        self.len += 1;
+    }
+
+    fn pop(&mut self) -> Cow<'a, Value> {
+        self.len -= 1;
+        match self.inline.get_mut(self.len) {
+            Some(slot) => slot.take(),
+            None => self.spilled.pop(),
+        }
+        .expect("operand evaluated")
+    }
+
+    fn last_mut(&mut self) -> &mut Cow<'a, Value> {
+        match self.inline.get_mut(self.len - 1) {
+            Some(slot) => slot.as_mut(),
+            None => self.spilled.last_mut(),
+        }
+        .expect("operand evaluated")
+    }
+
+    /// Pop the topmost `count` values, in the order they were pushed
+    fn split_off(&mut self, count: usize) -> Vec<Value> {
+        let mut values: Vec<Value> = (0..count).map(|_| self.pop().into_owned()).collect();
+        values.reverse();
+        values
+    }
+}
+
+impl Debug for CompiledExpr {
+    fn fmt(&self, fmt: &mut Formatter) -> fmt::Result {
+        fmt.debug_struct("CompiledExpr")
+            .field("slots", &self.slots)
+            .finish()
+    }
+}
+
+impl CompiledExpr {
+    /// Identifier names, in slot order
+    pub fn slots(&self) -> &[String] {
+        &self.slots
+    }
+
+    /// Slot index of the identifier `name`
+    pub fn slot(&self, name: &str) -> Option<usize> {
+        self.slots.iter().position(|s| s == name)
+    }
+
+    /// evaluate expression with `values[i]` as the value of slot `i`; slots past the end of
+    /// `values` are reported as `EvaluatorError::InvalidParameter` when they are read
+    pub fn evaluate(&self, values: &[Value]) -> Result<Value, LocatedError> {
+        self.locate(|slot| values.get(slot).map(Cow::Borrowed))
+    }
+
+    /// evaluate expression with slot values looked up from `parameters` when they are read;
+    /// as with `Evaluator::evaluate`, identifiers skipped by short-circuiting or a branch not
+    /// taken need not be defined
+    pub fn evaluate_with<C: Context + ?Sized>(
+        &self,
+        parameters: &C,
+    ) -> Result<Value, LocatedError> {
+        self.locate(|slot| parameters.get(&self.slots[slot]))
+    }
+
+    /// Run the instructions, locating the error if any
+    fn locate<'a>(
+        &'a self,
+        load: impl Fn(usize) -> Option<Cow<'a, Value>>,
+    ) -> Result<Value, LocatedError> {
+        let (mut at, mut operand) = (0, None);
+        self.run(load, &mut at, &mut operand)
+            .map_err(|error| LocatedError {
+                error,
+                path: self.path(self.code[at].2, operand),
+            })
+    }
+
+    /// Run the instructions with `load` giving the value of a slot, leaving the index of the
+    /// last one run in `at` and, if it failed on its constant operand, the step to that operand
+    /// in `operand`
+    fn run<'a>(
+        &'a self,
+        load: impl Fn(usize) -> Option<Cow<'a, Value>>,
+        at: &mut usize,
+        operand: &mut Option<NodeStep>,
+    ) -> Result<Value, EvaluatorError> {
+        let mut stack = Stack::new();
+        let mut budget = Budget::new(&self.limits);
+        let mut next = 0;
+
+        while let Some((instruction, depth, _)) = self.code.get(next) {
+            *at = next;
+            next += 1;
+            if let Err((error, step)) = instruction.charge(&mut budget, *depth) {
+                *operand = step;
+                return Err(error);
+            }
+            let value = match instruction {
+                Instruction::Value(v) => Cow::Borrowed(v),
+                Instruction::Load(slot, name) => {
+                    load(*slot).ok_or_else(|| EvaluatorError::InvalidParameter(name.clone()))?
+                }
+                Instruction::Path(slot, name, segments) => match load(*slot) {
+                    Some(Cow::Borrowed(root)) => Cow::Borrowed(resolve_path(name, root, segments)?),
+                    Some(Cow::Owned(root)) => {
+                        Cow::Owned(resolve_path(name, &root, segments)?.clone())
+                    }
+                    None => return Err(EvaluatorError::InvalidParameter(name.clone())),
+                },
+                Instruction::Fail(e) => return Err(e.clone()),
+                Instruction::ShortCircuit(o, end) => {
+                    let lr = stack.last_mut();
+                    if let Some(v) = short_circuit(lr, o) {
+                        *lr = Cow::Owned(v);
+                        next = *end;
+                    }
+                    continue;
+                }
+                Instruction::Binary(op) => {
+                    let rr = stack.pop();
+                    let lr = stack.pop();
+                    Cow::Owned(evaluate_binary_expr(&lr, op, &rr, &self.regexes)?)
+                }
+                Instruction::BinaryValue(op, rr) => {
+                    Cow::Owned(evaluate_binary_expr(&stack.pop(), op, rr, &self.regexes)?)
+                }
+                Instruction::ValueBinary(lr, op) => {
+                    Cow::Owned(evaluate_binary_expr(lr, op, &stack.pop(), &self.regexes)?)
+                }
+                Instruction::Unary(op) => Cow::Owned(evaluate_unary_expr(op, &stack.pop())?),
+                Instruction::Matches(regex, pattern) => match stack.pop().as_ref() {
+                    Value::String(l) => Cow::Owned(Value::Bool(regex.is_match(l))),
+                    l => {
+                        return Err(EvaluatorError::InvalidOperation(
+                            l.clone(),
+                            Op::Relational(RelationalOp::Matches),
+                            pattern.clone(),
+                        ))
+                    }
+                },
+                Instruction::Branch(otherwise) => {
+                    match stack.pop().as_ref() {
+                        Value::Bool(true) => {}
+                        Value::Bool(false) => next = *otherwise,
+                        v => return Err(EvaluatorError::InvalidCondition(v.clone())),
+                    }
+                    continue;
+                }
+                Instruction::Jump(end) => {
+                    next = *end;
+                    continue;
+                }
+                Instruction::CallHost(name, function, count) => {
+                    Cow::Owned(call_function(name, function, &stack.split_off(*count))?)
+                }
+                Instruction::CallBuiltin(name, function, count) => {
+                    Cow::Owned((function.func)(name, &stack.split_off(*count))?)
+                }
+            };
+            stack.push(value);
+        }
+
+        Ok(stack.pop().into_owned())
+    }
+
+    /// Location of `node`, or of its operand at `operand`
+    fn path(&self, mut node: usize, operand: Option<NodeStep>) -> NodePath {
+        let mut steps: Vec<NodeStep> = operand.into_iter().collect();
+        while node > 0 {
+            let (parent, step) = self.nodes[node - 1];
+            steps.push(step);
+            node = parent;
+        }
+        steps.reverse();
+        NodePath(steps)
+    }
+}
+
+impl Evaluator {
+    /// Compile `expr`, binding function calls to the functions and evaluation to the limits of
+    /// this evaluator
+    pub fn compile(&self, expr: &Expr) -> CompiledExpr {
+        let mut compiler = Compiler {
+            evaluator: self,
+            slots: vec![],
+            code: vec![],
+            nodes: vec![],
+            jumps: vec![],
+        };
+        compiler.compile(expr);
+
+        CompiledExpr {
+            slots: compiler.slots,
+            code: compiler.code,
+            nodes: compiler.nodes,
+            limits: *self.limits(),
+            regexes: self.regexes().clone(),
+        }
+    }
+}
+
+/// Compile expression with the built-in functions and no limits
+pub fn compile(expr: &Expr) -> CompiledExpr {
+    with_default_evaluator(|evaluator| evaluator.compile(expr))
+}
+
+struct Compiler<'a> {
+    evaluator: &'a Evaluator,
+    slots: Vec<String>,
+    code: Vec<(Instruction, usize, usize)>,
+    nodes: Vec<(usize, NodeStep)>,
+    /// Jumps emitted ahead of their target, innermost last
+    jumps: Vec<usize>,
+}
+
+/// Unit of work of the non-recursive compiler
+enum Task<'e> {
+    /// Compile an expression nested at the given depth, as the given node
+    Compile(&'e Expr, usize, usize),
+    Emit(Instruction, usize, usize),
+    /// Emit a jump whose target is set by a later `Land`
+    Jump(Instruction, usize, usize),
+    /// Emit a jump to the end of the conditional, and land the jump to its else branch here
+    Else(usize),
+    /// Set the target of the innermost pending jump to the next instruction
+    Land,
+}
+
+impl Compiler<'_> {
+    fn slot(&mut self, name: &str) -> usize {
+        match self.slots.iter().position(|s| s == name) {
+            Some(slot) => slot,
+            None => {
+                self.slots.push(name.to_owned());
+                self.slots.len() - 1
+            }
+        }
+    }
+
+    fn compile(&mut self, expr: &Expr) {
+        let mut tasks = vec![Task::Compile(expr, 1, 0)];
+        while let Some(task) = tasks.pop() {
+            match task {
+                Task::Compile(expr, depth, node) => {
+                    self.compile_node(expr, depth, node, &mut tasks)
+                }
+                Task::Emit(instruction, depth, node) => self.code.push((instruction, depth, node)),
+                Task::Jump(instruction, depth, node) => {
+                    self.jumps.push(self.code.len());
+                    self.code.push((instruction, depth, node));
+                }
+                Task::Else(node) => {
+                    let otherwise = self.jumps.pop().expect("pending branch");
+                    self.jumps.push(self.code.len());
+                    self.code.push((Instruction::Jump(0), 0, node));
+                    self.land(otherwise);
+                }
+                Task::Land => {
+                    let jump = self.jumps.pop().expect("pending jump");
+                    self.land(jump);
+                }
+            }
+        }
+    }
+
+    /// New node reached from `parent` by `step`
+    fn node(&mut self, parent: usize, step: NodeStep) -> usize {
+        self.nodes.push((parent, step));
+        self.nodes.len()
+    }
+
+    /// Emit the leaves of `expr` and schedule the instructions of its operands, in reverse
+    /// order
+    fn compile_node<'e>(
+        &mut self,
+        expr: &'e Expr,
+        depth: usize,
+        node: usize,
+        tasks: &mut Vec<Task<'e>>,
+    ) {
+        let operand = depth + 1;
+        let instruction = match expr {
+            Expr::Value(v) => Instruction::Value(v.clone()),
+            Expr::Identifier(name) => Instruction::Load(self.slot(name), name.clone()),
+            Expr::Path(name, segments) => {
+                Instruction::Path(self.slot(name), name.clone(), segments.clone())
+            }
+            Expr::Op(lhs, Op::Logical(o), rhs) => {
+                tasks.extend([
+                    Task::Land,
+                    Task::Emit(Instruction::Binary(Op::Logical(*o)), depth, node),
+                    Task::Compile(rhs, operand, self.node(node, NodeStep::Rhs)),
+                    Task::Jump(Instruction::ShortCircuit(*o, 0), depth, node),
+                    Task::Compile(lhs, operand, self.node(node, NodeStep::Lhs)),
+                ]);
+                return;
+            }
+            Expr::Op(lhs, op, rhs) => {
+                // constant operands are borrowed instead of cloned on every evaluation
+                let (instruction, operand_expr, step) = match (lhs.as_ref(), rhs.as_ref()) {
+                    (lhs, Expr::Value(v)) => (binary_value(*op, v), lhs, NodeStep::Lhs),
+                    (Expr::Value(v), rhs) => {
+                        (Instruction::ValueBinary(v.clone(), *op), rhs, NodeStep::Rhs)
+                    }
+                    (lhs, rhs) => {
+                        tasks.extend([
+                            Task::Emit(Instruction::Binary(*op), depth, node),
+                            Task::Compile(rhs, operand, self.node(node, NodeStep::Rhs)),
+                            Task::Compile(lhs, operand, self.node(node, NodeStep::Lhs)),
+                        ]);
+                        return;
+                    }
+                };
+                tasks.extend([
+                    Task::Emit(instruction, depth, node),
+                    Task::Compile(operand_expr, operand, self.node(node, step)),
+                ]);
+                return;
+            }
+            Expr::Unary(op, rhs) => {
+                tasks.extend([
+                    Task::Emit(Instruction::Unary(*op), depth, node),
+                    Task::Compile(rhs, operand, self.node(node, NodeStep::Rhs)),
+                ]);
+                return;
+            }
+            Expr::Call(name, args) => {
+                let count = args.len();
+                let call = match self.evaluator.callee(name, count) {
+                    Ok(Callee::Host(function)) => {
+                        Instruction::CallHost(name.clone(), function.clone(), count)
+                    }
+                    Ok(Callee::Builtin(function)) => {
+                        Instruction::CallBuiltin(name.clone(), function, count)
+                    }
+                    // unknown functions and wrong argument counts fail before the arguments
+                    // are evaluated
+                    Err(e) => {
+                        self.code.push((Instruction::Fail(e), depth, node));
+                        return;
+                    }
+                };
+                tasks.push(Task::Emit(call, depth, node));
+                for (i, arg) in args.iter().enumerate().rev() {
+                    tasks.push(Task::Compile(
+                        arg,
+                        operand,
+                        self.node(node, NodeStep::Arg(i)),
+                    ));
+                }
+                return;
+            }
+            Expr::Conditional(cond, then, otherwise) => {
+                tasks.extend([
+                    Task::Land,
+                    Task::Compile(otherwise, operand, self.node(node, NodeStep::Else)),
+                    Task::Else(node),
+                    Task::Compile(then, operand, self.node(node, NodeStep::Then)),
+                    Task::Jump(Instruction::Branch(0), depth, node),
+                    Task::Compile(cond, operand, self.node(node, NodeStep::If)),
+                ]);
+                return;
+            }
+        };
+        self.code.push((instruction, depth, node));
+    }
+
+    /// Point the jump at `at` to the next instruction
+    fn land(&mut self, at: usize) {
+        let end = self.code.len();
+        match &mut self.code[at].0 {
+            Instruction::ShortCircuit(_, target)
+            | Instruction::Branch(target)
+            | Instruction::Jump(target) => *target = end,
+            _ => unreachable!("not a jump"),
+        }
+    }
+}
+
+/// Operation with a constant right operand; constant patterns are compiled once instead of
+/// going through the regex cache of the evaluator
+fn binary_value(op: Op, rhs: &Value) -> Instruction {
+    if let (Op::Relational(RelationalOp::Matches), Value::String(pattern)) = (op, rhs) {
+        if let Ok(regex) = Regex::new(pattern) {
+            return Instruction::Matches(regex, rhs.clone());
+        }
+    }
+    Instruction::BinaryValue(op, rhs.clone())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::op::AdditiveOp;
+    use crate::evaluator::evaluator::evaluate;
+    use crate::evaluator::function::Signature;
+    use crate::parser::parser;
+    use crate::test_util::{deep_exprs, deep_parameters, PASS_DEPTH};
+    use crate::ValueType;
+    use std::collections::HashMap;
+    use std::time::Duration;
+
+    #[test]
+    fn test_compile_slots() {
+        let expr = parser::parse_expr_from_str("{a} + {b.c} * {a} > len({d})").unwrap();
+        let compiled = compile(&expr);
+        assert_eq!(compiled.slots(), ["a", "b", "d"]);
+        assert_eq!(compiled.slot("d"), Some(2));
+        assert_eq!(compiled.slot("c"), None);
+
+        let values = [Value::from(1), Value::from(&serde_json::json!({ "c": 2 }))];
+        assert_eq!(
+            compiled.evaluate(&values),
+            Err(LocatedError {
+                error: EvaluatorError::InvalidParameter("d".to_owned()),
+                path: NodePath(vec![NodeStep::Rhs, NodeStep::Arg(0)]),
+            })
+        );
+        assert_eq!(
+            compiled.evaluate_with(&HashMap::from([("a", Value::from(1))])),
+            Err(LocatedError {
+                error: EvaluatorError::InvalidParameter("b".to_owned()),
+                path: NodePath(vec![NodeStep::Lhs, NodeStep::Rhs, NodeStep::Lhs]),
+            })
+        );
+
+        let expr = parser::parse_expr_from_str("{b} && {missing}").unwrap();
+        let compiled = compile(&expr);
+        assert_eq!(
+            compiled.evaluate(&[Value::from(false)]),
+            Ok(Value::from(false))
+        );
+        assert_eq!(
+            compiled.evaluate(&[Value::from(true)]),
+            Err(LocatedError {
+                error: EvaluatorError::InvalidParameter("missing".to_owned()),
+                path: NodePath(vec![NodeStep::Rhs]),
+            })
+        );
+    }
+
+    #[test]
+    fn test_compile_skipped_identifiers() {
+        let parameters = HashMap::from([("a", Value::from(1))]);
+        let exprs = [
+            "false && {x}",
+            "true || {x.y}",
+            "{a} > 0 || {x}",
+            "if {a} > 0 then {a} else {x}",
+            "if false then {x} else {a}",
+            "{a} > 0 && {x}",
+            "if {x} then 1 else {y}",
+        ];
+        exprs.iter().for_each(|s| {
+            let expr = parser::parse_expr_from_str(s).unwrap();
+            assert_eq!(
+                compile(&expr).evaluate_with(&parameters),
+                evaluate(&expr, &parameters),
+                "expr: {}",
+                s
+            );
+        });
+        assert_eq!(
+            compile(&parser::parse_expr_from_str("false && {x}").unwrap())
+                .evaluate_with(&HashMap::<&str, Value>::new()),
+            Ok(Value::from(false))
+        );
+    }
+
+    #[test]
+    fn test_compile_expr() {
+        let evaluator = Evaluator::new().with_function(
+            "double",
+            Signature::new(vec![ValueType::Number]),
+            |args| match &args[0] {
+                Value::Number(v) => Ok(Value::from(v * 2.0)),
+                _ => Err("not a number"),
+            },
+        );
+        let parameters = HashMap::from([
+            ("a", Value::from(2)),
+            ("b", Value::from(false)),
+            ("name", Value::from("AB-1234")),
+            (
+                "user",
+                Value::from(&serde_json::json!({ "tags": ["vip"], "age": 30 })),
+            ),
+        ]);
+        let exprs = vec![
+            "{a} + 3 * 2 - -1",
+            "{a} / 0",
+            "{a} % 2 == 0 && !{b}",
+            "{a} || true",
+            "if {a} > 1 then 'big' else 'small'",
+            "if {name} then 1 else 2",
+            "if {b} then 1 else if {a} > 1 then max(2, {a}) else 3",
+            "{b} || {a} > 1 && !{b} || {a} == 1",
+            "{name} matches '^[A-Z]{2}-\\\\d{4}$'",
+            "{a} matches '^2$'",
+            "{name} matches {name}",
+            "{name} matches '('",
+            "{user.tags[0]} in ['vip', 'beta']",
+            "{user.tags[1]}",
+            "{user.age} >= 18",
+            "double({a}) + max(1, {a}, 5)",
+            "double({name})",
+            "double()",
+            "len()",
+            "unknown({a})",
+            "upper({name}) == 'AB-1234'",
+            "{a} + 'x'",
+        ];
+        exprs.iter().for_each(|s| {
+            let expr = parser::parse_expr_from_str(s).unwrap();
+            let compiled = evaluator.compile(&expr);
+            assert_eq!(
+                evaluator.evaluate(&expr, &parameters),
+                compiled.evaluate_with(&parameters),
+                "expr: {}",
+                s
+            );
+        });
+    }
+
+    #[test]
+    fn test_compile_deep_expr() {
+        let parameters = deep_parameters();
+        for (s, expected) in deep_exprs(PASS_DEPTH) {
+            let expr = parser::parse_expr_from_str(&s).unwrap();
+            let compiled = compile(&expr);
+            assert_eq!(compiled.evaluate_with(&parameters), Ok(expected));
+        }
+
+        let mut expr = Expr::Value(Value::from(0));
+        for _ in 0..PASS_DEPTH {
+            expr = Expr::Op(
+                Box::new(Expr::Identifier("n".to_owned())),
+                Op::Additive(AdditiveOp::Add),
+                Box::new(expr),
+            );
+        }
+        assert_eq!(
+            compile(&expr).evaluate_with(&parameters),
+            Ok(Value::from(PASS_DEPTH as f64))
+        );
+    }
+
+    #[test]
+    fn test_compile_with_limits() {
+        let parameters = HashMap::from([("a", Value::from(1)), ("s", Value::from("x"))]);
+        let exprs = vec![
+            "-(-(-{a})) + 1",
+            "false && {a} + {a} + {a} > 1",
+            "{a} > 0 || {a} + 1 > 2",
+            "{a} in [1, 2, 3] && 'x' == {s}",
+            "if {a} > 0 then max({a}, 2) else 0",
+            "{s} matches '^x$'",
+        ];
+        for s in exprs {
+            let expr = parser::parse_expr_from_str(s).unwrap();
+            let mut limits: Vec<Limits> = (0..12)
+                .map(|steps| Limits::new().with_max_steps(steps))
+                .chain((0..6).map(|depth| Limits::new().with_max_depth(depth)))
+                .collect();
+            limits.push(Limits::new().with_timeout(Duration::ZERO));
+            for limits in limits {
+                let evaluator = Evaluator::new().with_limits(limits);
+                assert_eq!(
+                    // steps are taken in another order, so only the errors are the same
+                    evaluator
+                        .compile(&expr)
+                        .evaluate_with(&parameters)
+                        .map_err(|e| e.error),
+                    evaluator.evaluate(&expr, &parameters).map_err(|e| e.error),
+                    "expr: {}, limits: {:?}",
+                    s,
+                    limits
+                );
+            }
+        }
+
+        let expr = parser::parse_expr_from_str("-(-(-{a})) + 1").unwrap();
+        let limits = Limits::new().with_max_steps(1);
+        assert_eq!(
+            compile(&expr).evaluate_with(&parameters),
+            Ok(Value::from(0))
+        );
+        assert_eq!(
+            Evaluator::new()
+                .with_limits(limits)
+                .compile(&expr)
+                .evaluate_with(&parameters),
+            Err(LocatedError {
+                error: EvaluatorError::StepLimitExceeded(1),
+                path: NodePath(vec![NodeStep::Lhs, NodeStep::Rhs, NodeStep::Rhs]),
+            })
+        );
+    }
+}
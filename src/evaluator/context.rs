@@ -0,0 +1,116 @@
+use serde_json::{Map, Value as JsonValue};
+use std::borrow::Cow;
+use std::collections::{BTreeMap, HashMap};
+use std::hash::BuildHasher;
+
+use crate::ast::value::Value;
+
+/// Source of identifier values used by the evaluator.
+///
+/// Implemented for the common map types, `serde_json::Value` objects and closures, so values can
+/// be resolved lazily from the caller's own data structures.
+///
+/// # Examples
+///
+/// ```
+/// use evaluator_rs::*;
+///
+/// let expr = parse_expr_from_str("{a} + {b}").unwrap();
+/// let context = |name: &str| match name {
+///     "a" => Some(Value::from(1)),
+///     "b" => Some(Value::from(2)),
+///     _ => None,
+/// };
+/// assert_eq!(evaluate(&expr, &context).unwrap(), Value::from(3));
+/// ```
+pub trait Context {
+    /// Value of the identifier `name`, if it is defined
+    fn get(&self, name: &str) -> Option<Cow<'_, Value>>;
+}
+
+impl<S: BuildHasher> Context for HashMap<&str, Value, S> {
+    fn get(&self, name: &str) -> Option<Cow<'_, Value>> {
+        HashMap::get(self, name).map(Cow::Borrowed)
+    }
+}
+
+impl<S: BuildHasher> Context for HashMap<String, Value, S> {
+    fn get(&self, name: &str) -> Option<Cow<'_, Value>> {
+        HashMap::get(self, name).map(Cow::Borrowed)
+    }
+}
+
+impl Context for BTreeMap<&str, Value> {
+    fn get(&self, name: &str) -> Option<Cow<'_, Value>> {
+        BTreeMap::get(self, name).map(Cow::Borrowed)
+    }
+}
+
+impl Context for BTreeMap<String, Value> {
+    fn get(&self, name: &str) -> Option<Cow<'_, Value>> {
+        BTreeMap::get(self, name).map(Cow::Borrowed)
+    }
+}
+
+/// Fields of a JSON object, converted on lookup
+impl Context for Map<String, JsonValue> {
+    fn get(&self, name: &str) -> Option<Cow<'_, Value>> {
+        Map::get(self, name).map(|v| Cow::Owned(Value::from(v)))
+    }
+}
+
+/// Fields of a JSON object, converted on lookup; other JSON values define no identifiers
+impl Context for JsonValue {
+    fn get(&self, name: &str) -> Option<Cow<'_, Value>> {
+        match self {
+            JsonValue::Object(object) => Context::get(object, name),
+            _ => None,
+        }
+    }
+}
+
+impl<F> Context for F
+where
+    F: Fn(&str) -> Option<Value>,
+{
+    fn get(&self, name: &str) -> Option<Cow<'_, Value>> {
+        self(name).map(Cow::Owned)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lookup<C: Context + ?Sized>(context: &C, name: &str) -> Option<Value> {
+        context.get(name).map(Cow::into_owned)
+    }
+
+    #[test]
+    fn test_context() {
+        let context: HashMap<&str, Value> = HashMap::from([("a", Value::from(1))]);
+        assert_eq!(lookup(&context, "a"), Some(Value::from(1)));
+        assert_eq!(lookup(&context, "b"), None);
+
+        let context: HashMap<String, Value> = HashMap::from([("a".to_owned(), Value::from(1))]);
+        assert_eq!(lookup(&context, "a"), Some(Value::from(1)));
+
+        let context: BTreeMap<&str, Value> = BTreeMap::from([("a", Value::from(1))]);
+        assert_eq!(lookup(&context, "a"), Some(Value::from(1)));
+
+        let context: BTreeMap<String, Value> = BTreeMap::from([("a".to_owned(), Value::from(1))]);
+        assert_eq!(lookup(&context, "a"), Some(Value::from(1)));
+
+        let context = serde_json::json!({ "a": 1, "b": { "c": [true] } });
+        assert_eq!(lookup(&context, "a"), Some(Value::from(1)));
+        assert_eq!(
+            lookup(&context, "b"),
+            Some(Value::from(&serde_json::json!({ "c": [true] })))
+        );
+        assert_eq!(lookup(&serde_json::json!([1]), "a"), None);
+
+        let context = |name: &str| (name == "a").then(|| Value::from(1));
+        assert_eq!(lookup(&context, "a"), Some(Value::from(1)));
+        assert_eq!(lookup(&context, "b"), None);
+    }
+}
@@ -1,21 +1,58 @@
-use std::collections::HashMap;
+use regex::Regex;
+use serde::{de::DeserializeOwned, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::fmt::{self, Debug, Formatter};
+use std::ops::Range;
+use std::sync::Arc;
+use std::time::Duration;
 use thiserror::Error;
 
 use crate::ast::{
-    expr::Expr,
-    op::{AdditiveOp, LogicalOp, MultiplicativeOp, Op, RelationalOp},
+    expr::{Expr, NodePath, NodeStep, PathSegment},
+    op::{AdditiveOp, LogicalOp, MultiplicativeOp, Op, RelationalOp, UnaryOp},
     value::Value,
 };
+use crate::evaluator::context::Context;
+use crate::evaluator::function::{builtin, Arity, Builtin, Function, FunctionError, Signature};
+use crate::evaluator::limits::{Budget, Limits};
+use crate::evaluator::regex_cache::RegexCache;
+use crate::parser::parser::SourceMap;
 
-#[derive(Error, Debug, PartialEq)]
+#[derive(Error, Debug, Clone, PartialEq)]
 pub enum EvaluatorError {
     #[error("invalid parameter {0}")]
     InvalidParameter(String),
     #[error("invalid operation {0} {1} {2}")]
     InvalidOperation(Value, Op, Value),
+    #[error("invalid operation {0}{1}")]
+    InvalidUnaryOperation(UnaryOp, Value),
+    #[error("invalid path {0}: cannot resolve {1}")]
+    InvalidPath(String, PathSegment),
+    #[error("unknown function {0}")]
+    UnknownFunction(String),
+    #[error("function {0} expects {1} arguments, got {2}")]
+    InvalidArgumentCount(String, Arity, usize),
+    #[error("invalid argument {1} for function {0}")]
+    InvalidArgument(String, Value),
+    #[error("function {0} failed: {1}")]
+    FunctionFailed(String, #[source] FunctionError),
+    #[error("invalid condition {0}")]
+    InvalidCondition(Value),
+    #[error("invalid regex {0}: {1}")]
+    InvalidRegex(String, String),
+    #[error("invalid parameters: {0}")]
+    InvalidParameters(String),
+    #[error("expected {0} result, got {1}")]
+    UnexpectedType(String, Value),
+    #[error("expression nesting exceeds the limit of {0}")]
+    DepthLimitExceeded(usize),
+    #[error("evaluation exceeds the limit of {0} steps")]
+    StepLimitExceeded(usize),
+    #[error("evaluation timed out after {0:?}")]
+    Timeout(Duration),
 }
 
-/// evaluate expresstion with parameters
+/// Evaluator with host functions registered on top of the built-in ones
 ///
 /// Examples:
 ///
@@ -23,36 +60,696 @@ pub enum EvaluatorError {
 /// use evaluator_rs::*;
 /// use std::collections::HashMap;
 ///
-/// let expr = parse_expr_from_str("{a} + 2 + 3").unwrap();
-/// let parameters = HashMap::from([("a", Value::from(1))]);
-/// let rs = evaluate(&expr, &parameters).unwrap();
-/// assert_eq!(rs, Value::from(6));
+/// let evaluator = Evaluator::new().with_function(
+///     "risk_score",
+///     Signature::new(vec![ValueType::String]),
+///     |args| match &args[0] {
+///         Value::String(id) if id == "u1" => Ok(Value::from(0.9)),
+///         _ => Err("unknown user"),
+///     },
+/// );
+///
+/// let expr = parse_expr_from_str("risk_score({user_id}) > 0.5").unwrap();
+/// let parameters = HashMap::from([("user_id", Value::from("u1"))]);
+/// let rs = evaluator.evaluate(&expr, &parameters).unwrap();
+/// assert_eq!(rs, Value::from(true));
 /// ```
-pub fn evaluate(expr: &Expr, parameters: &HashMap<&str, Value>) -> Result<Value, EvaluatorError> {
+#[derive(Clone, Default)]
+pub struct Evaluator {
+    functions: HashMap<String, Function>,
+    limits: Limits,
+    regexes: Arc<RegexCache>,
+}
+
+impl Debug for Evaluator {
+    fn fmt(&self, fmt: &mut Formatter) -> fmt::Result {
+        fmt.debug_struct("Evaluator")
+            .field("functions", &self.functions.keys().collect::<Vec<_>>())
+            .field("limits", &self.limits)
+            .field("regexes", &self.regexes)
+            .finish()
+    }
+}
+
+impl Evaluator {
+    pub fn new() -> Self {
+        Evaluator::default()
+    }
+
+    /// Register a host function, replacing any built-in or earlier function with the same name.
+    /// Arguments are checked against `signature` before `func` is called and errors returned by
+    /// `func` are reported as `EvaluatorError::FunctionFailed`.
+    pub fn with_function<F, E>(mut self, name: &str, signature: Signature, func: F) -> Self
+    where
+        F: Fn(&[Value]) -> Result<Value, E> + Send + Sync + 'static,
+        E: Into<Box<dyn std::error::Error + Send + Sync>>,
+    {
+        let func = move |args: &[Value]| func(args).map_err(FunctionError::new);
+        self.functions.insert(
+            name.to_owned(),
+            Function {
+                signature,
+                func: Arc::new(func),
+            },
+        );
+        self
+    }
+
+    /// Enforce the depth, step and timeout `limits` on every evaluation; exceeding them is
+    /// reported as `EvaluatorError::DepthLimitExceeded`, `StepLimitExceeded` or `Timeout`
+    pub fn with_limits(mut self, limits: Limits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    /// Compile the patterns of `matches` through `cache` instead of a cache owned by this
+    /// evaluator, sharing it with every evaluator holding it
+    pub fn with_regex_cache(mut self, cache: Arc<RegexCache>) -> Self {
+        self.regexes = cache;
+        self
+    }
+
+    /// Budget of one evaluation under the limits of this evaluator
+    pub(crate) fn budget(&self) -> Budget {
+        Budget::new(&self.limits)
+    }
+
+    pub(crate) fn limits(&self) -> &Limits {
+        &self.limits
+    }
+
+    pub(crate) fn regexes(&self) -> &Arc<RegexCache> {
+        &self.regexes
+    }
+
+    /// Host function registered under `name`
+    pub(crate) fn function(&self, name: &str) -> Option<&Function> {
+        self.functions.get(name)
+    }
+
+    /// evaluate expression with parameters; errors are located at the subexpression that fails
+    pub fn evaluate<C: Context + ?Sized>(
+        &self,
+        expr: &Expr,
+        parameters: &C,
+    ) -> Result<Value, LocatedError> {
+        self.evaluate_node(expr, parameters, &mut self.budget())
+            .map_err(|(error, steps)| LocatedError {
+                error,
+                path: NodePath(steps),
+            })
+    }
+
+    /// evaluate expression with the fields of a serializable value as parameters
+    pub fn evaluate_serializable<T: Serialize + ?Sized>(
+        &self,
+        expr: &Expr,
+        parameters: &T,
+    ) -> Result<Value, LocatedError> {
+        let parameters = serde_json::to_value(parameters)
+            .map_err(|e| EvaluatorError::InvalidParameters(e.to_string()))?;
+        self.evaluate(expr, &parameters)
+    }
+
+    /// evaluate expression to a boolean
+    pub fn evaluate_bool<C: Context + ?Sized>(
+        &self,
+        expr: &Expr,
+        parameters: &C,
+    ) -> Result<bool, LocatedError> {
+        Ok(self.evaluate(expr, parameters)?.try_into()?)
+    }
+
+    /// evaluate expression to a number
+    pub fn evaluate_number<C: Context + ?Sized>(
+        &self,
+        expr: &Expr,
+        parameters: &C,
+    ) -> Result<f64, LocatedError> {
+        Ok(self.evaluate(expr, parameters)?.try_into()?)
+    }
+
+    /// evaluate expression to a string
+    pub fn evaluate_string<C: Context + ?Sized>(
+        &self,
+        expr: &Expr,
+        parameters: &C,
+    ) -> Result<String, LocatedError> {
+        Ok(self.evaluate(expr, parameters)?.try_into()?)
+    }
+
+    /// evaluate expression to an array
+    pub fn evaluate_array<C: Context + ?Sized>(
+        &self,
+        expr: &Expr,
+        parameters: &C,
+    ) -> Result<Vec<Value>, LocatedError> {
+        match self.evaluate(expr, parameters)? {
+            Value::Array(v) => Ok(v),
+            v => Err(EvaluatorError::UnexpectedType("array".to_owned(), v).into()),
+        }
+    }
+
+    /// evaluate expression and deserialize the result into `T`
+    pub fn evaluate_as<T: DeserializeOwned, C: Context + ?Sized>(
+        &self,
+        expr: &Expr,
+        parameters: &C,
+    ) -> Result<T, LocatedError> {
+        let value = self.evaluate(expr, parameters)?;
+        serde_json::to_value(&value)
+            .and_then(serde_json::from_value)
+            .map_err(|_| {
+                EvaluatorError::UnexpectedType(std::any::type_name::<T>().to_owned(), value).into()
+            })
+    }
+
+    /// Evaluate `expr` with an explicit work stack instead of recursion, so that arbitrarily
+    /// deep expressions do not overflow the stack
+    fn evaluate_node<C: Context + ?Sized>(
+        &self,
+        expr: &Expr,
+        parameters: &C,
+        budget: &mut Budget,
+    ) -> Result<Value, NodeError> {
+        let mut tasks = Vec::with_capacity(8);
+        let mut values = Vec::with_capacity(4);
+        let mut depth = 1;
+
+        tasks.push(Task::Eval(expr));
+        while let Some(task) = tasks.pop() {
+            let rs = match task {
+                // operands without operands of their own are evaluated right away
+                Task::Enter(
+                    step,
+                    expr @ (Expr::Identifier(_) | Expr::Path(_, _) | Expr::Value(_)),
+                ) => {
+                    let rs = budget
+                        .step(depth + 1)
+                        .and_then(|()| evaluate_leaf(expr, parameters))
+                        .map(|v| values.push(v));
+                    if rs.is_err() {
+                        tasks.push(Task::Leave(step));
+                    }
+                    rs
+                }
+                Task::Enter(step, expr) => {
+                    depth += 1;
+                    tasks.push(Task::Leave(step));
+                    self.run_task(
+                        Task::Eval(expr),
+                        parameters,
+                        budget,
+                        &mut tasks,
+                        &mut values,
+                        depth,
+                    )
+                }
+                Task::Leave(_) => {
+                    depth -= 1;
+                    Ok(())
+                }
+                task => self.run_task(task, parameters, budget, &mut tasks, &mut values, depth),
+            };
+            // the steps still to leave are the path from `expr` down to the failing operand
+            rs.map_err(|error| {
+                let steps = tasks.iter().filter_map(|task| match task {
+                    Task::Leave(step) => Some(*step),
+                    _ => None,
+                });
+                (error, steps.collect())
+            })?;
+        }
+
+        Ok(values.pop().expect("expression evaluated"))
+    }
+
+    /// Run one task of an expression at `depth`, pushing the tasks it depends on and the value it
+    /// results in. Tasks that combine operands run after them, back at their own expression.
+    fn run_task<'e, C: Context + ?Sized>(
+        &'e self,
+        task: Task<'e>,
+        parameters: &C,
+        budget: &mut Budget,
+        tasks: &mut Vec<Task<'e>>,
+        values: &mut Vec<Value>,
+        depth: usize,
+    ) -> Result<(), EvaluatorError> {
+        let value = match task {
+            Task::Eval(expr) => {
+                budget.step(depth)?;
+                match expr {
+                    Expr::Identifier(_) | Expr::Path(_, _) | Expr::Value(_) => {
+                        evaluate_leaf(expr, parameters)?
+                    }
+                    Expr::Op(lhs, Op::Logical(o), rhs) => {
+                        tasks.push(Task::Logical(*o, rhs));
+                        tasks.push(Task::Enter(NodeStep::Lhs, lhs));
+                        return Ok(());
+                    }
+                    Expr::Op(lhs, op, rhs) => {
+                        tasks.push(Task::Binary(*op));
+                        tasks.push(Task::Enter(NodeStep::Rhs, rhs));
+                        tasks.push(Task::Enter(NodeStep::Lhs, lhs));
+                        return Ok(());
+                    }
+                    Expr::Unary(op, rhs) => {
+                        tasks.push(Task::Unary(*op));
+                        tasks.push(Task::Enter(NodeStep::Rhs, rhs));
+                        return Ok(());
+                    }
+                    Expr::Call(name, args) => {
+                        let callee = self.callee(name, args.len())?;
+                        tasks.push(Task::Call(name, callee, args.len()));
+                        for (i, arg) in args.iter().enumerate().rev() {
+                            tasks.push(Task::Enter(NodeStep::Arg(i), arg));
+                        }
+                        return Ok(());
+                    }
+                    Expr::Conditional(cond, then, otherwise) => {
+                        tasks.push(Task::Branch(then, otherwise));
+                        tasks.push(Task::Enter(NodeStep::If, cond));
+                        return Ok(());
+                    }
+                }
+            }
+            Task::Logical(o, rhs) => {
+                let lr = values.last().expect("operand evaluated");
+                if let Some(v) = short_circuit(lr, &o) {
+                    values.pop();
+                    v
+                } else {
+                    tasks.push(Task::Binary(Op::Logical(o)));
+                    tasks.push(Task::Enter(NodeStep::Rhs, rhs));
+                    return Ok(());
+                }
+            }
+            Task::Binary(op) => {
+                let rr = values.pop().expect("operand evaluated");
+                let lr = values.pop().expect("operand evaluated");
+                evaluate_binary_expr(&lr, &op, &rr, &self.regexes)?
+            }
+            Task::Unary(op) => {
+                let rr = values.pop().expect("operand evaluated");
+                evaluate_unary_expr(&op, &rr)?
+            }
+            Task::Branch(then, otherwise) => match values.pop().expect("condition evaluated") {
+                Value::Bool(true) => {
+                    tasks.push(Task::Enter(NodeStep::Then, then));
+                    return Ok(());
+                }
+                Value::Bool(false) => {
+                    tasks.push(Task::Enter(NodeStep::Else, otherwise));
+                    return Ok(());
+                }
+                v => return Err(EvaluatorError::InvalidCondition(v)),
+            },
+            Task::Call(name, callee, count) => {
+                let args = values.split_off(values.len() - count);
+                callee.call(name, &args)?
+            }
+            Task::Enter(_, _) | Task::Leave(_) => unreachable!("handled by evaluate_node"),
+        };
+
+        values.push(value);
+        Ok(())
+    }
+
+    /// Function called by `name` with `count` arguments, host functions first
+    pub(crate) fn callee(&self, name: &str, count: usize) -> Result<Callee<'_>, EvaluatorError> {
+        if let Some(function) = self.functions.get(name) {
+            let arity = function.signature.arity();
+            if !arity.accepts(count) {
+                return Err(EvaluatorError::InvalidArgumentCount(
+                    name.to_owned(),
+                    arity,
+                    count,
+                ));
+            }
+            return Ok(Callee::Host(function));
+        }
+
+        let function =
+            builtin(name).ok_or_else(|| EvaluatorError::UnknownFunction(name.to_owned()))?;
+        if !function.arity.accepts(count) {
+            return Err(EvaluatorError::InvalidArgumentCount(
+                name.to_owned(),
+                function.arity,
+                count,
+            ));
+        }
+        Ok(Callee::Builtin(function))
+    }
+}
+
+/// Value of an identifier, path or value expression
+pub(crate) fn evaluate_leaf<C: Context + ?Sized>(
+    expr: &Expr,
+    parameters: &C,
+) -> Result<Value, EvaluatorError> {
     match expr {
-        Expr::Identifier(name) => match parameters.get(name.as_str()) {
-            Some(v) => Ok(v.clone()),
+        Expr::Identifier(name) => match parameters.get(name) {
+            Some(v) => Ok(v.into_owned()),
             None => Err(EvaluatorError::InvalidParameter(name.to_string())),
         },
-        Expr::Op(ref lhs, op, ref rhs) => evaluate_op(lhs, op, rhs, parameters),
+        Expr::Path(name, segments) => evaluate_path(name, segments, parameters),
         Expr::Value(v) => Ok(v.clone()),
+        _ => unreachable!("not a leaf expression"),
     }
 }
 
-fn evaluate_op(
-    lhs: &Expr,
-    op: &Op,
-    rhs: &Expr,
-    parameters: &HashMap<&str, Value>,
+/// Error with the steps from the evaluated expression down to the failing subexpression
+type NodeError = (EvaluatorError, Vec<NodeStep>);
+
+/// Unit of work of the non-recursive evaluator
+enum Task<'e> {
+    /// Evaluate an expression, pushing its value
+    Eval(&'e Expr),
+    /// Evaluate the operand reached by a step from the current expression
+    Enter(NodeStep, &'e Expr),
+    /// Step back up from an evaluated operand
+    Leave(NodeStep),
+    /// Short-circuit on the left operand or evaluate the right one
+    Logical(LogicalOp, &'e Expr),
+    /// Combine the two topmost values
+    Binary(Op),
+    Unary(UnaryOp),
+    /// Evaluate one branch depending on the condition value
+    Branch(&'e Expr, &'e Expr),
+    /// Call a function with the topmost values as arguments
+    Call(&'e str, Callee<'e>, usize),
+}
+
+/// Function resolved for a call, with the argument count already checked
+pub(crate) enum Callee<'e> {
+    Host(&'e Function),
+    Builtin(&'static Builtin),
+}
+
+impl Callee<'_> {
+    pub fn call(&self, name: &str, args: &[Value]) -> Result<Value, EvaluatorError> {
+        match self {
+            Callee::Host(function) => call_function(name, function, args),
+            Callee::Builtin(function) => (function.func)(name, args),
+        }
+    }
+}
+
+/// Error with the location of the subexpression it applies to.
+///
+/// `path` also locates the subexpression in the JSON expression format; for expressions parsed
+/// from a string, `Located::span` gives its position in the source.
+#[derive(Error, Debug, Clone, PartialEq)]
+#[error("{error} at {path}")]
+pub struct Located<E> {
+    pub error: E,
+    pub path: NodePath,
+}
+
+/// Evaluation error with the location of the subexpression that failed
+pub type LocatedError = Located<EvaluatorError>;
+
+impl<E> Located<E> {
+    /// Byte span of the subexpression in the source of an expression parsed with
+    /// `parse_expr_with_source_map`
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use evaluator_rs::*;
+    /// use std::collections::HashMap;
+    ///
+    /// let source = "{a} > 1 && {b} + 1 > 2";
+    /// let (expr, source_map) = parse_expr_with_source_map(source).unwrap();
+    /// let error = evaluate(&expr, &HashMap::from([("a", Value::from(2))])).unwrap_err();
+    /// assert_eq!(error.span(&source_map).map(|span| &source[span]), Some("{b}"));
+    /// ```
+    pub fn span(&self, source_map: &SourceMap) -> Option<Range<usize>> {
+        source_map.span(&self.path)
+    }
+}
+
+/// Error of the expression as a whole, such as a result of the wrong type, located at its root
+impl From<EvaluatorError> for LocatedError {
+    fn from(error: EvaluatorError) -> Self {
+        LocatedError {
+            error,
+            path: NodePath::default(),
+        }
+    }
+}
+
+/// Evaluator of the free functions, one per thread so that its regex cache outlives a single
+/// call without being shared across threads
+pub(crate) fn with_default_evaluator<R>(f: impl FnOnce(&Evaluator) -> R) -> R {
+    thread_local! {
+        static EVALUATOR: Evaluator = Evaluator::new();
+    }
+    EVALUATOR.with(f)
+}
+
+/// evaluate expression with parameters
+///
+/// Examples:
+///
+/// ```
+/// use evaluator_rs::*;
+/// use std::collections::HashMap;
+///
+/// let expr = parse_expr_from_str("{a} + 2 + 3").unwrap();
+/// let parameters = HashMap::from([("a", Value::from(1))]);
+/// let rs = evaluate(&expr, &parameters).unwrap();
+/// assert_eq!(rs, Value::from(6));
+/// ```
+pub fn evaluate<C: Context + ?Sized>(expr: &Expr, parameters: &C) -> Result<Value, LocatedError> {
+    with_default_evaluator(|evaluator| evaluator.evaluate(expr, parameters))
+}
+
+/// evaluate expression with the fields of a serializable value as parameters
+///
+/// Examples:
+///
+/// ```
+/// use evaluator_rs::*;
+/// use serde::Serialize;
+///
+/// #[derive(Serialize)]
+/// struct Address {
+///     city: String,
+/// }
+///
+/// #[derive(Serialize)]
+/// struct User {
+///     age: u32,
+///     address: Address,
+/// }
+///
+/// let user = User { age: 21, address: Address { city: "Hanoi".to_owned() } };
+/// let expr = parse_expr_from_str("{age} >= 18 && {address.city} == 'Hanoi'").unwrap();
+/// let rs = evaluate_serializable(&expr, &user).unwrap();
+/// assert_eq!(rs, Value::from(true));
+/// ```
+pub fn evaluate_serializable<T: Serialize + ?Sized>(
+    expr: &Expr,
+    parameters: &T,
+) -> Result<Value, LocatedError> {
+    with_default_evaluator(|evaluator| evaluator.evaluate_serializable(expr, parameters))
+}
+
+/// evaluate expression to a boolean
+///
+/// Examples:
+///
+/// ```
+/// use evaluator_rs::*;
+/// use std::collections::HashMap;
+///
+/// let expr = parse_expr_from_str("{a} > 1").unwrap();
+/// let parameters = HashMap::from([("a", Value::from(2))]);
+/// assert!(evaluate_bool(&expr, &parameters).unwrap());
+/// ```
+pub fn evaluate_bool<C: Context + ?Sized>(
+    expr: &Expr,
+    parameters: &C,
+) -> Result<bool, LocatedError> {
+    with_default_evaluator(|evaluator| evaluator.evaluate_bool(expr, parameters))
+}
+
+/// evaluate expression to a number
+pub fn evaluate_number<C: Context + ?Sized>(
+    expr: &Expr,
+    parameters: &C,
+) -> Result<f64, LocatedError> {
+    with_default_evaluator(|evaluator| evaluator.evaluate_number(expr, parameters))
+}
+
+/// evaluate expression to a string
+pub fn evaluate_string<C: Context + ?Sized>(
+    expr: &Expr,
+    parameters: &C,
+) -> Result<String, LocatedError> {
+    with_default_evaluator(|evaluator| evaluator.evaluate_string(expr, parameters))
+}
+
+/// evaluate expression to an array
+pub fn evaluate_array<C: Context + ?Sized>(
+    expr: &Expr,
+    parameters: &C,
+) -> Result<Vec<Value>, LocatedError> {
+    with_default_evaluator(|evaluator| evaluator.evaluate_array(expr, parameters))
+}
+
+/// evaluate expression and deserialize the result into `T`
+///
+/// Examples:
+///
+/// ```
+/// use evaluator_rs::*;
+/// use std::collections::HashMap;
+///
+/// let expr = parse_expr_from_str("{a} * 2").unwrap();
+/// let parameters = HashMap::from([("a", Value::from(21))]);
+/// let rs: u32 = evaluate_as(&expr, &parameters).unwrap();
+/// assert_eq!(rs, 42);
+/// ```
+pub fn evaluate_as<T: DeserializeOwned, C: Context + ?Sized>(
+    expr: &Expr,
+    parameters: &C,
+) -> Result<T, LocatedError> {
+    with_default_evaluator(|evaluator| evaluator.evaluate_as(expr, parameters))
+}
+
+/// Check that `parameters` defines every identifier of the expression, reporting all missing
+/// names at once. Each missing name is reported as `EvaluatorError::InvalidParameter`, located
+/// at its first reference in evaluation order.
+///
+/// Examples:
+///
+/// ```
+/// use evaluator_rs::*;
+/// use std::collections::HashMap;
+///
+/// let expr = parse_expr_from_str("{a} + {b.c} > {d}").unwrap();
+/// let parameters = HashMap::from([("a", Value::from(1))]);
+/// let errors = validate_parameters(&expr, &parameters).unwrap_err();
+/// let errors: Vec<String> = errors.iter().map(ToString::to_string).collect();
+/// assert_eq!(errors, ["invalid parameter b at /lhs/rhs", "invalid parameter d at /rhs"]);
+/// ```
+pub fn validate_parameters<C: Context + ?Sized>(
+    expr: &Expr,
+    parameters: &C,
+) -> Result<(), Vec<LocatedError>> {
+    let mut reported = HashSet::new();
+    let mut missing = vec![];
+    expr.visit(|expr, path| {
+        if let Expr::Identifier(name) | Expr::Path(name, _) = expr {
+            if parameters.get(name).is_none() && reported.insert(name) {
+                missing.push(LocatedError {
+                    error: EvaluatorError::InvalidParameter(name.clone()),
+                    path: NodePath(path.to_vec()),
+                });
+            }
+        }
+    });
+
+    match missing.is_empty() {
+        true => Ok(()),
+        false => Err(missing),
+    }
+}
+
+/// Check the arguments of a host function against its signature and call it
+pub(crate) fn call_function(
+    name: &str,
+    function: &Function,
+    args: &[Value],
+) -> Result<Value, EvaluatorError> {
+    for (i, arg) in args.iter().enumerate() {
+        if !function.signature.param(i).map_or(true, |t| t.matches(arg)) {
+            return Err(EvaluatorError::InvalidArgument(
+                name.to_owned(),
+                arg.clone(),
+            ));
+        }
+    }
+
+    (function.func)(args).map_err(|e| EvaluatorError::FunctionFailed(name.to_owned(), e))
+}
+
+fn evaluate_path<C: Context + ?Sized>(
+    name: &str,
+    segments: &[PathSegment],
+    parameters: &C,
 ) -> Result<Value, EvaluatorError> {
-    let lr = evaluate(lhs, parameters)?;
-    let rr = evaluate(rhs, parameters)?;
+    let root = parameters
+        .get(name)
+        .ok_or_else(|| EvaluatorError::InvalidParameter(name.to_string()))?;
+    resolve_path(name, &root, segments).cloned()
+}
 
+/// Walk `segments` from the value of the identifier `name`
+pub(crate) fn resolve_path<'a>(
+    name: &str,
+    root: &'a Value,
+    segments: &[PathSegment],
+) -> Result<&'a Value, EvaluatorError> {
+    let mut current = root;
+
+    for segment in segments {
+        let next = match (current, segment) {
+            (Value::Object(v), PathSegment::Field(field)) => v.get(field),
+            (Value::Array(v), PathSegment::Index(index)) => v.get(*index),
+            _ => None,
+        };
+        current = next.ok_or_else(|| {
+            let path = segments.iter().fold(name.to_string(), |mut path, s| {
+                path.push_str(&s.to_string());
+                path
+            });
+            EvaluatorError::InvalidPath(path, segment.clone())
+        })?;
+    }
+
+    Ok(current)
+}
+
+/// Evaluate a binary operation, compiling the patterns of `matches` through `regexes`
+pub(crate) fn evaluate_binary_expr(
+    lhs: &Value,
+    op: &Op,
+    rhs: &Value,
+    regexes: &RegexCache,
+) -> Result<Value, EvaluatorError> {
     match op {
-        Op::Logical(o) => evaluate_logical_expr(&lr, o, &rr),
-        Op::Relational(o) => evaluate_relational_expr(&lr, o, &rr),
-        Op::Additive(o) => evaluate_additive_expr(&lr, o, &rr),
-        Op::Multiplicative(o) => evaluate_multiplicative_expr(&lr, o, &rr),
+        Op::Relational(RelationalOp::Matches) => match (lhs, rhs) {
+            (Value::String(l), Value::String(r)) => Ok(Value::Bool(regexes.get(r)?.is_match(l))),
+            _ => Err(EvaluatorError::InvalidOperation(
+                lhs.clone(),
+                *op,
+                rhs.clone(),
+            )),
+        },
+        Op::Logical(o) => evaluate_logical_expr(lhs, o, rhs),
+        Op::Relational(o) => evaluate_relational_expr(lhs, o, rhs),
+        Op::Additive(o) => evaluate_additive_expr(lhs, o, rhs),
+        Op::Multiplicative(o) => evaluate_multiplicative_expr(lhs, o, rhs),
+    }
+}
+
+pub(crate) fn evaluate_unary_expr(op: &UnaryOp, rhs: &Value) -> Result<Value, EvaluatorError> {
+    match (op, rhs) {
+        (UnaryOp::Not, Value::Bool(r)) => Ok(Value::Bool(!*r)),
+        (UnaryOp::Neg, Value::Number(r)) => Ok(Value::Number(-*r)),
+        _ => Err(EvaluatorError::InvalidUnaryOperation(*op, rhs.clone())),
+    }
+}
+
+/// Result of a logical operation decided by its left operand alone
+pub(crate) fn short_circuit(lhs: &Value, op: &LogicalOp) -> Option<Value> {
+    match (lhs, op) {
+        (Value::Bool(false), LogicalOp::And) => Some(Value::Bool(false)),
+        (Value::Bool(true), LogicalOp::Or) => Some(Value::Bool(true)),
+        _ => None,
     }
 }
 
@@ -81,6 +778,7 @@ fn evaluate_logical_expr(
     }
 }
 
+#[allow(clippy::bool_comparison)]
 fn evaluate_relational_expr(
     lhs: &Value,
     op: &RelationalOp,
@@ -132,6 +830,8 @@ fn evaluate_relational_expr(
             (Value::Number(l), Value::Number(r)) => Ok(Value::Bool(*l == *r)),
             (Value::String(l), Value::String(r)) => Ok(Value::Bool(*l == *r)),
             (Value::Array(l), Value::Array(r)) => Ok(Value::Bool(*l == *r)),
+            (Value::Object(l), Value::Object(r)) => Ok(Value::Bool(*l == *r)),
+            (Value::Null, _) | (_, Value::Null) => Ok(Value::Bool(lhs == rhs)),
             _ => Err(EvaluatorError::InvalidOperation(
                 lhs.clone(),
                 Op::Relational(*op),
@@ -143,6 +843,8 @@ fn evaluate_relational_expr(
             (Value::Number(l), Value::Number(r)) => Ok(Value::Bool(*l != *r)),
             (Value::String(l), Value::String(r)) => Ok(Value::Bool(*l != *r)),
             (Value::Array(l), Value::Array(r)) => Ok(Value::Bool(*l != *r)),
+            (Value::Object(l), Value::Object(r)) => Ok(Value::Bool(*l != *r)),
+            (Value::Null, _) | (_, Value::Null) => Ok(Value::Bool(lhs != rhs)),
             _ => Err(EvaluatorError::InvalidOperation(
                 lhs.clone(),
                 Op::Relational(*op),
@@ -153,12 +855,14 @@ fn evaluate_relational_expr(
             (Value::Number(_), Value::Array(r)) => Ok(Value::Bool(r.contains(lhs))),
             (Value::String(_), Value::Array(r)) => Ok(Value::Bool(r.contains(lhs))),
             (Value::Bool(_), Value::Array(r)) => Ok(Value::Bool(r.contains(lhs))),
+            (Value::Null, Value::Array(r)) => Ok(Value::Bool(r.contains(lhs))),
             _ => Err(EvaluatorError::InvalidOperation(
                 lhs.clone(),
                 Op::Relational(*op),
                 rhs.clone(),
             )),
         },
+        RelationalOp::Matches => unreachable!("matches is evaluated by evaluate_binary_expr"),
     }
 }
 
@@ -224,7 +928,8 @@ fn evaluate_multiplicative_expr(
 mod tests {
     use super::*;
     use crate::parser::parser;
-    use crate::Value;
+    use crate::test_util::{deep_exprs, deep_parameters, EVALUATE_DEPTH};
+    use crate::{Value, ValueType};
     use std::collections::HashMap;
 
     #[allow(dead_code)]
@@ -287,161 +992,325 @@ mod tests {
         ];
         test_cases.iter().for_each(|case| {
             let expr = parser::parse_expr_from_str(case.expr).unwrap();
-            let output = super::evaluate(&expr, &empty_parameters);
+            let output = super::evaluate(&expr, &empty_parameters).map_err(|e| e.error);
             assert_eq!(case.want, output, "expr: {}", case.expr);
         });
     }
 
     #[test]
-    fn test_additive_expr() {
-        let empty_parameters: HashMap<&str, Value> = HashMap::new();
-
+    fn test_short_circuit_expr() {
+        let evaluator = Evaluator::new().with_function("fail", Signature::new(vec![]), |_| {
+            Err::<Value, _>("fail must not be called")
+        });
+        let parameters = HashMap::from([("a", Value::from(0)), ("b", Value::from(5))]);
         let test_cases: Vec<TestCase> = vec![
             TestCase {
-                expr: "10 + 10",
-                want: Ok(Value::from(20.0)),
+                expr: "false && {missing}",
+                want: Ok(Value::from(false)),
             },
             TestCase {
-                expr: "10 - 10",
-                want: Ok(Value::from(0.0)),
+                expr: "true || {missing}",
+                want: Ok(Value::from(true)),
             },
             TestCase {
-                expr: "1.5 + 1.5",
-                want: Ok(Value::from(3.0)),
+                expr: "false && fail()",
+                want: Ok(Value::from(false)),
             },
             TestCase {
-                expr: "1.5 + 1",
-                want: Ok(Value::from(2.5)),
+                expr: "{a} == 0 || fail()",
+                want: Ok(Value::from(true)),
             },
             TestCase {
-                expr: "1 + 1.5",
-                want: Ok(Value::from(2.5)),
+                expr: "{a} != 0 && {b} / {a} > 1",
+                want: Ok(Value::from(false)),
             },
             TestCase {
-                expr: "3.5 - 1",
-                want: Ok(Value::from(2.5)),
+                expr: "false && fail() || true",
+                want: Ok(Value::from(true)),
             },
             TestCase {
-                expr: "3 - 1.5",
-                want: Ok(Value::from(1.5)),
+                expr: "true && {missing}",
+                want: Err(EvaluatorError::InvalidParameter("missing".to_owned())),
             },
             TestCase {
-                expr: "3.5 - 1.5",
-                want: Ok(Value::from(2.0)),
+                expr: "false || {missing}",
+                want: Err(EvaluatorError::InvalidParameter("missing".to_owned())),
             },
             TestCase {
-                expr: "1 + false",
-                want: Err(EvaluatorError::InvalidOperation(
-                    Value::from(1),
-                    Op::Additive(AdditiveOp::Add),
-                    Value::from(false),
-                )),
+                expr: "1 && {missing}",
+                want: Err(EvaluatorError::InvalidParameter("missing".to_owned())),
             },
             TestCase {
-                expr: "1 - false",
+                expr: "true && 1",
                 want: Err(EvaluatorError::InvalidOperation(
+                    Value::from(true),
+                    Op::Logical(LogicalOp::And),
                     Value::from(1),
-                    Op::Additive(AdditiveOp::Sub),
-                    Value::from(false),
                 )),
             },
         ];
         test_cases.iter().for_each(|case| {
             let expr = parser::parse_expr_from_str(case.expr).unwrap();
-            let output = super::evaluate(&expr, &empty_parameters);
+            let output = evaluator.evaluate(&expr, &parameters).map_err(|e| e.error);
             assert_eq!(case.want, output, "expr: {}", case.expr);
         });
     }
 
     #[test]
-    fn test_multiplicative_expr() {
-        let empty_parameters: HashMap<&str, Value> = HashMap::new();
-
+    fn test_conditional_expr() {
+        let evaluator = Evaluator::new().with_function("fail", Signature::new(vec![]), |_| {
+            Err::<Value, _>("fail must not be called")
+        });
+        let parameters = HashMap::from([("tier", Value::from("gold")), ("price", Value::from(50))]);
         let test_cases: Vec<TestCase> = vec![
             TestCase {
-                expr: "10 * 10",
-                want: Ok(Value::from(100.0)),
-            },
-            TestCase {
-                expr: "10 / 10",
-                want: Ok(Value::from(1.0)),
-            },
-            TestCase {
-                expr: "1.1 * 2.0",
-                want: Ok(Value::from(2.2)),
+                expr: "if true then 1 else 2",
+                want: Ok(Value::from(1)),
             },
             TestCase {
-                expr: "1.1 * 2",
-                want: Ok(Value::from(2.2)),
+                expr: "if 1 > 2 then 1 else 2",
+                want: Ok(Value::from(2)),
             },
             TestCase {
-                expr: "2 * 1.1",
-                want: Ok(Value::from(2.2)),
+                expr: "(if {price} >= 100 then 0.1 else 0.05) * {price}",
+                want: Ok(Value::from(2.5)),
             },
             TestCase {
-                expr: "10 % 3",
+                expr: "if {price} > 10 then 1 else if {price} > 5 then 2 else 3",
                 want: Ok(Value::from(1)),
             },
             TestCase {
-                expr: "10 % 2.5",
-                want: Ok(Value::from(0)),
+                expr: "if true then 1 else fail()",
+                want: Ok(Value::from(1)),
             },
             TestCase {
-                expr: "1 * true",
-                want: Err(EvaluatorError::InvalidOperation(
-                    Value::from(1),
-                    Op::Multiplicative(MultiplicativeOp::Mul),
-                    Value::from(true),
-                )),
+                expr: "if false then {missing} else 'x'",
+                want: Ok(Value::from("x")),
             },
             TestCase {
-                expr: "1 / true",
-                want: Err(EvaluatorError::InvalidOperation(
-                    Value::from(1),
-                    Op::Multiplicative(MultiplicativeOp::Div),
-                    Value::from(true),
-                )),
+                expr: "if true then {missing} else 1",
+                want: Err(EvaluatorError::InvalidParameter("missing".to_owned())),
             },
             TestCase {
-                expr: "1 % false",
-                want: Err(EvaluatorError::InvalidOperation(
-                    Value::from(1),
-                    Op::Multiplicative(MultiplicativeOp::Mod),
-                    Value::from(false),
-                )),
+                expr: "if {price} then 1 else 2",
+                want: Err(EvaluatorError::InvalidCondition(Value::from(50))),
             },
         ];
         test_cases.iter().for_each(|case| {
             let expr = parser::parse_expr_from_str(case.expr).unwrap();
-            let output = super::evaluate(&expr, &empty_parameters);
+            let output = evaluator.evaluate(&expr, &parameters).map_err(|e| e.error);
             assert_eq!(case.want, output, "expr: {}", case.expr);
         });
     }
 
     #[test]
-    fn test_equality_expr() {
+    fn test_unary_expr() {
         let empty_parameters: HashMap<&str, Value> = HashMap::new();
-
         let test_cases: Vec<TestCase> = vec![
             TestCase {
-                expr: "10 == 10",
-                want: Ok(Value::from(true)),
-            },
-            TestCase {
-                expr: "10 == 1",
+                expr: "!true",
                 want: Ok(Value::from(false)),
             },
             TestCase {
-                expr: "10 != 10",
-                want: Ok(Value::from(false)),
+                expr: "!!true",
+                want: Ok(Value::from(true)),
             },
             TestCase {
-                expr: "1 != 10",
+                expr: "!(1 > 2)",
                 want: Ok(Value::from(true)),
             },
             TestCase {
-                expr: "10.2 != 12.2",
-                want: Ok(Value::from(true)),
+                expr: "-5",
+                want: Ok(Value::from(-5)),
+            },
+            TestCase {
+                expr: "--5",
+                want: Ok(Value::from(5)),
+            },
+            TestCase {
+                expr: "-1.5 + 1",
+                want: Ok(Value::from(-0.5)),
+            },
+            TestCase {
+                expr: "2 - -3",
+                want: Ok(Value::from(5)),
+            },
+            TestCase {
+                expr: "-(2 + 3) * 2",
+                want: Ok(Value::from(-10)),
+            },
+            TestCase {
+                expr: "!1",
+                want: Err(EvaluatorError::InvalidUnaryOperation(
+                    UnaryOp::Not,
+                    Value::from(1),
+                )),
+            },
+            TestCase {
+                expr: "-true",
+                want: Err(EvaluatorError::InvalidUnaryOperation(
+                    UnaryOp::Neg,
+                    Value::from(true),
+                )),
+            },
+        ];
+        test_cases.iter().for_each(|case| {
+            let expr = parser::parse_expr_from_str(case.expr).unwrap();
+            let output = super::evaluate(&expr, &empty_parameters).map_err(|e| e.error);
+            assert_eq!(case.want, output, "expr: {}", case.expr);
+        });
+    }
+
+    #[test]
+    fn test_additive_expr() {
+        let empty_parameters: HashMap<&str, Value> = HashMap::new();
+
+        let test_cases: Vec<TestCase> = vec![
+            TestCase {
+                expr: "10 + 10",
+                want: Ok(Value::from(20.0)),
+            },
+            TestCase {
+                expr: "10 - 10",
+                want: Ok(Value::from(0.0)),
+            },
+            TestCase {
+                expr: "1.5 + 1.5",
+                want: Ok(Value::from(3.0)),
+            },
+            TestCase {
+                expr: "1.5 + 1",
+                want: Ok(Value::from(2.5)),
+            },
+            TestCase {
+                expr: "1 + 1.5",
+                want: Ok(Value::from(2.5)),
+            },
+            TestCase {
+                expr: "3.5 - 1",
+                want: Ok(Value::from(2.5)),
+            },
+            TestCase {
+                expr: "3 - 1.5",
+                want: Ok(Value::from(1.5)),
+            },
+            TestCase {
+                expr: "3.5 - 1.5",
+                want: Ok(Value::from(2.0)),
+            },
+            TestCase {
+                expr: "1 + false",
+                want: Err(EvaluatorError::InvalidOperation(
+                    Value::from(1),
+                    Op::Additive(AdditiveOp::Add),
+                    Value::from(false),
+                )),
+            },
+            TestCase {
+                expr: "1 - false",
+                want: Err(EvaluatorError::InvalidOperation(
+                    Value::from(1),
+                    Op::Additive(AdditiveOp::Sub),
+                    Value::from(false),
+                )),
+            },
+        ];
+        test_cases.iter().for_each(|case| {
+            let expr = parser::parse_expr_from_str(case.expr).unwrap();
+            let output = super::evaluate(&expr, &empty_parameters).map_err(|e| e.error);
+            assert_eq!(case.want, output, "expr: {}", case.expr);
+        });
+    }
+
+    #[test]
+    fn test_multiplicative_expr() {
+        let empty_parameters: HashMap<&str, Value> = HashMap::new();
+
+        let test_cases: Vec<TestCase> = vec![
+            TestCase {
+                expr: "10 * 10",
+                want: Ok(Value::from(100.0)),
+            },
+            TestCase {
+                expr: "10 / 10",
+                want: Ok(Value::from(1.0)),
+            },
+            TestCase {
+                expr: "1.1 * 2.0",
+                want: Ok(Value::from(2.2)),
+            },
+            TestCase {
+                expr: "1.1 * 2",
+                want: Ok(Value::from(2.2)),
+            },
+            TestCase {
+                expr: "2 * 1.1",
+                want: Ok(Value::from(2.2)),
+            },
+            TestCase {
+                expr: "10 % 3",
+                want: Ok(Value::from(1)),
+            },
+            TestCase {
+                expr: "10 % 2.5",
+                want: Ok(Value::from(0)),
+            },
+            TestCase {
+                expr: "1 * true",
+                want: Err(EvaluatorError::InvalidOperation(
+                    Value::from(1),
+                    Op::Multiplicative(MultiplicativeOp::Mul),
+                    Value::from(true),
+                )),
+            },
+            TestCase {
+                expr: "1 / true",
+                want: Err(EvaluatorError::InvalidOperation(
+                    Value::from(1),
+                    Op::Multiplicative(MultiplicativeOp::Div),
+                    Value::from(true),
+                )),
+            },
+            TestCase {
+                expr: "1 % false",
+                want: Err(EvaluatorError::InvalidOperation(
+                    Value::from(1),
+                    Op::Multiplicative(MultiplicativeOp::Mod),
+                    Value::from(false),
+                )),
+            },
+        ];
+        test_cases.iter().for_each(|case| {
+            let expr = parser::parse_expr_from_str(case.expr).unwrap();
+            let output = super::evaluate(&expr, &empty_parameters).map_err(|e| e.error);
+            assert_eq!(case.want, output, "expr: {}", case.expr);
+        });
+    }
+
+    #[test]
+    fn test_equality_expr() {
+        let empty_parameters: HashMap<&str, Value> = HashMap::new();
+
+        let test_cases: Vec<TestCase> = vec![
+            TestCase {
+                expr: "10 == 10",
+                want: Ok(Value::from(true)),
+            },
+            TestCase {
+                expr: "10 == 1",
+                want: Ok(Value::from(false)),
+            },
+            TestCase {
+                expr: "10 != 10",
+                want: Ok(Value::from(false)),
+            },
+            TestCase {
+                expr: "1 != 10",
+                want: Ok(Value::from(true)),
+            },
+            TestCase {
+                expr: "10.2 != 12.2",
+                want: Ok(Value::from(true)),
             },
             TestCase {
                 expr: "10.1 == 10.1",
@@ -479,6 +1348,10 @@ mod tests {
                 expr: "'hello' == 'hello'",
                 want: Ok(Value::from(true)),
             },
+            TestCase {
+                expr: "'hello' == \"hello\"",
+                want: Ok(Value::from(true)),
+            },
             TestCase {
                 expr: "'hello' == 'world'",
                 want: Ok(Value::from(false)),
@@ -487,6 +1360,34 @@ mod tests {
                 expr: "'hello' != 'world'",
                 want: Ok(Value::from(true)),
             },
+            TestCase {
+                expr: "null == null",
+                want: Ok(Value::from(true)),
+            },
+            TestCase {
+                expr: "null != null",
+                want: Ok(Value::from(false)),
+            },
+            TestCase {
+                expr: "1 == null",
+                want: Ok(Value::from(false)),
+            },
+            TestCase {
+                expr: "null != 'hello'",
+                want: Ok(Value::from(true)),
+            },
+            TestCase {
+                expr: "[1, null] == [1, null]",
+                want: Ok(Value::from(true)),
+            },
+            TestCase {
+                expr: "null > 1",
+                want: Err(EvaluatorError::InvalidOperation(
+                    Value::Null,
+                    Op::Relational(RelationalOp::Gt),
+                    Value::from(1),
+                )),
+            },
             TestCase {
                 expr: "1 == true",
                 want: Err(EvaluatorError::InvalidOperation(
@@ -514,7 +1415,7 @@ mod tests {
         ];
         test_cases.iter().for_each(|case| {
             let expr = parser::parse_expr_from_str(case.expr).unwrap();
-            let output = super::evaluate(&expr, &empty_parameters);
+            let output = super::evaluate(&expr, &empty_parameters).map_err(|e| e.error);
             assert_eq!(case.want, output, "expr: {}", case.expr);
         });
     }
@@ -683,7 +1584,7 @@ mod tests {
         ];
         test_cases.iter().for_each(|case| {
             let expr = parser::parse_expr_from_str(case.expr).unwrap();
-            let output = super::evaluate(&expr, &empty_parameters);
+            let output = super::evaluate(&expr, &empty_parameters).map_err(|e| e.error);
             assert_eq!(case.want, output, "expr: {}", case.expr);
         });
     }
@@ -721,14 +1622,90 @@ mod tests {
                 expr: "1 in []",
                 want: Ok(Value::from(false)),
             },
+            TestCase {
+                expr: "null in [1, null]",
+                want: Ok(Value::from(true)),
+            },
         ];
         test_cases.iter().for_each(|case| {
             let expr = parser::parse_expr_from_str(case.expr).unwrap();
-            let output = super::evaluate(&expr, &empty_parameters);
+            let output = super::evaluate(&expr, &empty_parameters).map_err(|e| e.error);
             assert_eq!(case.want, output, "expr: {}", case.expr);
         });
     }
 
+    #[test]
+    fn test_matches_expr() {
+        let parameters = HashMap::from([
+            ("email", Value::from("alice@example.com")),
+            ("sku", Value::from("AB-1234")),
+            ("email_pattern", Value::from(r"^[a-z]+@example\.com$")),
+            ("sku_pattern", Value::from(r"^[A-Z]{2}-\d{4}$")),
+            ("bad_pattern", Value::from("(")),
+        ]);
+        let test_cases: Vec<TestCase> = vec![
+            TestCase {
+                expr: "{email} matches {email_pattern}",
+                want: Ok(Value::from(true)),
+            },
+            TestCase {
+                expr: "{sku} =~ {sku_pattern}",
+                want: Ok(Value::from(true)),
+            },
+            TestCase {
+                expr: "{sku} matches {email_pattern}",
+                want: Ok(Value::from(false)),
+            },
+            TestCase {
+                expr: "{email} matches {email_pattern} && {sku} matches {sku_pattern}",
+                want: Ok(Value::from(true)),
+            },
+            TestCase {
+                expr: "1 matches {sku_pattern}",
+                want: Err(EvaluatorError::InvalidOperation(
+                    Value::from(1),
+                    Op::Relational(RelationalOp::Matches),
+                    Value::from(r"^[A-Z]{2}-\d{4}$"),
+                )),
+            },
+        ];
+        test_cases.iter().for_each(|case| {
+            let expr = parser::parse_expr_from_str(case.expr).unwrap();
+            let output = super::evaluate(&expr, &parameters).map_err(|e| e.error);
+            assert_eq!(case.want, output, "expr: {}", case.expr);
+        });
+
+        let expr = parser::parse_expr_from_str("{email} matches {bad_pattern}").unwrap();
+        match super::evaluate(&expr, &parameters).map_err(|e| e.error) {
+            Err(EvaluatorError::InvalidRegex(pattern, e)) => {
+                assert_eq!(pattern, "(");
+                assert!(e.contains("unclosed group"), "{}", e);
+            }
+            output => panic!("unexpected output {:?}", output),
+        }
+
+        let cache = Arc::new(RegexCache::new(1));
+        let evaluator = Evaluator::new().with_regex_cache(cache.clone());
+        let expr = parser::parse_expr_from_str("{email} matches {email_pattern}").unwrap();
+        assert_eq!(
+            evaluator.evaluate(&expr, &parameters),
+            Ok(Value::from(true))
+        );
+        assert!(cache.contains(r"^[a-z]+@example\.com$"));
+
+        let expr = parser::parse_expr_from_str("{sku} matches {sku_pattern}").unwrap();
+        assert_eq!(
+            evaluator.evaluate(&expr, &parameters),
+            Ok(Value::from(true))
+        );
+        assert!(cache.contains(r"^[A-Z]{2}-\d{4}$"));
+        assert_eq!(cache.len(), 1);
+
+        let expr = parser::parse_expr_from_str("{email} matches {bad_pattern}").unwrap();
+        assert!(evaluator.evaluate(&expr, &parameters).is_err());
+        assert!(!cache.contains("("));
+    }
+
     #[test]
     fn test_parameter_expr() {
         let empty_parameters: HashMap<&str, Value> = HashMap::new();
@@ -763,6 +1740,36 @@ mod tests {
                 parameters: HashMap::from([("name", Value::from(2))]),
                 want: Ok(Value::from(false)),
             },
+            TestCaseWithParameters {
+                expr: "'hello' == {name}",
+                parameters: HashMap::from([("name", Value::from("hello"))]),
+                want: Ok(Value::from(true)),
+            },
+            TestCaseWithParameters {
+                expr: "{name} == 'it\\'s'",
+                parameters: HashMap::from([("name", Value::from("it's"))]),
+                want: Ok(Value::from(true)),
+            },
+            TestCaseWithParameters {
+                expr: "{email} matches '^[a-z]+@example\\\\.com$'",
+                parameters: HashMap::from([("email", Value::from("bob@example.com"))]),
+                want: Ok(Value::from(true)),
+            },
+            TestCaseWithParameters {
+                expr: "{coupon} == null",
+                parameters: HashMap::from([("coupon", Value::from(&serde_json::Value::Null))]),
+                want: Ok(Value::from(true)),
+            },
+            TestCaseWithParameters {
+                expr: "!{is_banned}",
+                parameters: HashMap::from([("is_banned", Value::from(false))]),
+                want: Ok(Value::from(true)),
+            },
+            TestCaseWithParameters {
+                expr: "-{delta} > 3",
+                parameters: HashMap::from([("delta", Value::from(-4))]),
+                want: Ok(Value::from(true)),
+            },
             TestCaseWithParameters {
                 expr: "{name} == 1",
                 parameters: empty_parameters,
@@ -772,9 +1779,659 @@ mod tests {
 
         test_cases.iter().for_each(|case| {
             let expr = parser::parse_expr_from_str(case.expr).unwrap();
-            let output = super::evaluate(&expr, &case.parameters);
+            let output = super::evaluate(&expr, &case.parameters).map_err(|e| e.error);
+            assert_eq!(case.want, output, "expr: {}", case.expr);
+        });
+    }
+
+    #[test]
+    fn test_path_expr() {
+        let parameters = HashMap::from([
+            (
+                "user",
+                Value::from(&serde_json::json!({
+                    "name": "alice",
+                    "address": { "city": "Hanoi", "zip": null },
+                    "tags": ["vip", "beta"],
+                })),
+            ),
+            (
+                "items",
+                Value::from(&serde_json::json!([{ "price": 10 }, { "price": 25 }])),
+            ),
+        ]);
+        let test_cases: Vec<TestCase> = vec![
+            TestCase {
+                expr: "{user.address.city}",
+                want: Ok(Value::from("Hanoi")),
+            },
+            TestCase {
+                expr: "{user.address.zip} == null",
+                want: Ok(Value::from(true)),
+            },
+            TestCase {
+                expr: "{user.tags[1]}",
+                want: Ok(Value::from("beta")),
+            },
+            TestCase {
+                expr: "{items[0].price} + {items[1].price}",
+                want: Ok(Value::from(35)),
+            },
+            TestCase {
+                expr: "{user.address} == {user.address}",
+                want: Ok(Value::from(true)),
+            },
+            TestCase {
+                expr: "{user.address.street}",
+                want: Err(EvaluatorError::InvalidPath(
+                    "user.address.street".to_owned(),
+                    PathSegment::Field("street".to_owned()),
+                )),
+            },
+            TestCase {
+                expr: "{items[2].price}",
+                want: Err(EvaluatorError::InvalidPath(
+                    "items[2].price".to_owned(),
+                    PathSegment::Index(2),
+                )),
+            },
+            TestCase {
+                expr: "{user.name[0]}",
+                want: Err(EvaluatorError::InvalidPath(
+                    "user.name[0]".to_owned(),
+                    PathSegment::Index(0),
+                )),
+            },
+            TestCase {
+                expr: "{order.id}",
+                want: Err(EvaluatorError::InvalidParameter("order".to_owned())),
+            },
+        ];
+        test_cases.iter().for_each(|case| {
+            let expr = parser::parse_expr_from_str(case.expr).unwrap();
+            let output = super::evaluate(&expr, &parameters).map_err(|e| e.error);
+            assert_eq!(case.want, output, "expr: {}", case.expr);
+        });
+    }
+
+    #[test]
+    fn test_context_expr() {
+        let json = serde_json::json!({
+            "user": { "name": "alice", "tags": ["vip"] },
+            "limit": 3,
+        });
+        let lookup = |name: &str| match name {
+            "user" => Some(Value::from(
+                &serde_json::json!({ "name": "alice", "tags": ["vip"] }),
+            )),
+            "limit" => Some(Value::from(3)),
+            _ => None,
+        };
+        let test_cases: Vec<TestCase> = vec![
+            TestCase {
+                expr: "{user.name} == 'alice'",
+                want: Ok(Value::from(true)),
+            },
+            TestCase {
+                expr: "{user.tags[0]}",
+                want: Ok(Value::from("vip")),
+            },
+            TestCase {
+                expr: "{limit} * 2",
+                want: Ok(Value::from(6)),
+            },
+            TestCase {
+                expr: "{missing}",
+                want: Err(EvaluatorError::InvalidParameter("missing".to_owned())),
+            },
+        ];
+        test_cases.iter().for_each(|case| {
+            let expr = parser::parse_expr_from_str(case.expr).unwrap();
+            assert_eq!(
+                case.want,
+                super::evaluate(&expr, &json).map_err(|e| e.error),
+                "expr: {}",
+                case.expr
+            );
+            assert_eq!(
+                case.want,
+                super::evaluate(&expr, &lookup).map_err(|e| e.error),
+                "expr: {}",
+                case.expr
+            );
+        });
+    }
+
+    #[test]
+    fn test_serializable_expr() {
+        #[derive(serde::Serialize)]
+        struct Order {
+            id: u64,
+            items: Vec<Item>,
+            note: Option<String>,
+            tags: HashMap<String, bool>,
+        }
+
+        #[derive(serde::Serialize)]
+        struct Item {
+            price: f64,
+        }
+
+        let order = Order {
+            id: 7,
+            items: vec![Item { price: 10.0 }, Item { price: 2.5 }],
+            note: None,
+            tags: HashMap::from([("gift".to_owned(), true)]),
+        };
+        let test_cases: Vec<TestCase> = vec![
+            TestCase {
+                expr: "{id} == 7",
+                want: Ok(Value::from(true)),
+            },
+            TestCase {
+                expr: "{items[0].price} + {items[1].price}",
+                want: Ok(Value::from(12.5)),
+            },
+            TestCase {
+                expr: "{note} == null",
+                want: Ok(Value::from(true)),
+            },
+            TestCase {
+                expr: "{tags.gift}",
+                want: Ok(Value::from(true)),
+            },
+            TestCase {
+                expr: "{customer}",
+                want: Err(EvaluatorError::InvalidParameter("customer".to_owned())),
+            },
+        ];
+        test_cases.iter().for_each(|case| {
+            let expr = parser::parse_expr_from_str(case.expr).unwrap();
+            let output = super::evaluate_serializable(&expr, &order).map_err(|e| e.error);
+            assert_eq!(case.want, output, "expr: {}", case.expr);
+        });
+
+        let expr = parser::parse_expr_from_str("{a}").unwrap();
+        let parameters = HashMap::from([((1, 2), 3)]);
+        assert!(matches!(
+            super::evaluate_serializable(&expr, &parameters).map_err(|e| e.error),
+            Err(EvaluatorError::InvalidParameters(_))
+        ));
+    }
+
+    #[test]
+    fn test_typed_expr() {
+        let parameters = HashMap::from([
+            ("a", Value::from(2)),
+            ("name", Value::from("alice")),
+            (
+                "user",
+                Value::from(&serde_json::json!({ "id": 7, "tags": ["vip"] })),
+            ),
+        ]);
+        let parse = |s| parser::parse_expr_from_str(s).unwrap();
+
+        assert_eq!(
+            super::evaluate_bool(&parse("{a} > 1"), &parameters),
+            Ok(true)
+        );
+        assert_eq!(
+            super::evaluate_number(&parse("{a} * 1.5"), &parameters),
+            Ok(3.0)
+        );
+        assert_eq!(
+            super::evaluate_string(&parse("upper({name})"), &parameters),
+            Ok("ALICE".to_owned())
+        );
+        assert_eq!(
+            super::evaluate_array(&parse("{user.tags}"), &parameters),
+            Ok(vec![Value::from("vip")])
+        );
+        assert_eq!(
+            super::evaluate_bool(&parse("{a} + 1"), &parameters),
+            Err(EvaluatorError::UnexpectedType("bool".to_owned(), Value::from(3)).into())
+        );
+        assert_eq!(
+            super::evaluate_array(&parse("{name}"), &parameters),
+            Err(EvaluatorError::UnexpectedType("array".to_owned(), Value::from("alice")).into())
+        );
+
+        #[derive(serde::Deserialize, Debug, PartialEq)]
+        struct User {
+            id: u32,
+            tags: Vec<String>,
+        }
+        assert_eq!(
+            super::evaluate_as::<User, _>(&parse("{user}"), &parameters),
+            Ok(User {
+                id: 7,
+                tags: vec!["vip".to_owned()],
+            })
+        );
+        assert_eq!(
+            super::evaluate_as::<u32, _>(&parse("{name}"), &parameters),
+            Err(EvaluatorError::UnexpectedType("u32".to_owned(), Value::from("alice")).into())
+        );
+    }
+
+    #[test]
+    fn test_validate_parameters() {
+        let expr =
+            parser::parse_expr_from_str("{a} > 1 && {user.name} == {b} || {a} < {c}").unwrap();
+        let parameters = HashMap::from([("a", Value::from(1)), ("c", Value::from(2))]);
+        let output = super::validate_parameters(&expr, &parameters);
+        assert_eq!(
+            output,
+            Err(vec![
+                LocatedError {
+                    error: EvaluatorError::InvalidParameter("user".to_owned()),
+                    path: NodePath(vec![NodeStep::Lhs, NodeStep::Rhs, NodeStep::Lhs]),
+                },
+                LocatedError {
+                    error: EvaluatorError::InvalidParameter("b".to_owned()),
+                    path: NodePath(vec![NodeStep::Lhs, NodeStep::Rhs, NodeStep::Rhs]),
+                },
+            ])
+        );
+
+        let parameters = HashMap::from([
+            ("a", Value::from(1)),
+            ("b", Value::from("x")),
+            ("c", Value::from(2)),
+            ("user", Value::Null),
+        ]);
+        assert_eq!(super::validate_parameters(&expr, &parameters), Ok(()));
+    }
+
+    #[test]
+    fn test_limits_expr() {
+        let parameters = HashMap::from([("a", Value::from(1))]);
+        let expr = parser::parse_expr_from_str("-(-(-{a})) + 1").unwrap();
+        let test_cases: Vec<(Limits, Result<Value, EvaluatorError>)> = vec![
+            (Limits::new(), Ok(Value::from(0))),
+            (Limits::new().with_max_depth(5), Ok(Value::from(0))),
+            (
+                Limits::new().with_max_depth(4),
+                Err(EvaluatorError::DepthLimitExceeded(4)),
+            ),
+            (Limits::new().with_max_steps(6), Ok(Value::from(0))),
+            (
+                Limits::new().with_max_steps(5),
+                Err(EvaluatorError::StepLimitExceeded(5)),
+            ),
+            (
+                Limits::new().with_timeout(Duration::ZERO),
+                Err(EvaluatorError::Timeout(Duration::ZERO)),
+            ),
+            (
+                Limits::new().with_timeout(Duration::from_secs(60)),
+                Ok(Value::from(0)),
+            ),
+            (
+                Limits::new().with_timeout(Duration::MAX),
+                Ok(Value::from(0)),
+            ),
+        ];
+        test_cases.into_iter().for_each(|(limits, expected)| {
+            let evaluator = Evaluator::new().with_limits(limits);
+            assert_eq!(
+                evaluator.evaluate(&expr, &parameters).map_err(|e| e.error),
+                expected,
+                "limits: {:?}",
+                limits
+            );
+        });
+
+        // short-circuited operands are not evaluated and take no steps
+        let expr = parser::parse_expr_from_str("false && {a} + {a} + {a} > 1").unwrap();
+        let evaluator = Evaluator::new().with_limits(Limits::new().with_max_steps(3));
+        assert_eq!(
+            evaluator.evaluate(&expr, &parameters),
+            Ok(Value::from(false))
+        );
+
+        let expr = parser::parse_expr_from_str("{a} + {a} > 1 && false").unwrap();
+        let evaluator = Evaluator::new().with_limits(Limits::new().with_max_depth(2));
+        assert_eq!(
+            evaluator.evaluate(&expr, &parameters),
+            Err(LocatedError {
+                error: EvaluatorError::DepthLimitExceeded(2),
+                path: NodePath(vec![NodeStep::Lhs, NodeStep::Lhs]),
+            })
+        );
+    }
+
+    #[test]
+    fn test_deep_expr() {
+        let parameters = deep_parameters();
+        for (s, expected) in deep_exprs(EVALUATE_DEPTH) {
+            let expr = parser::parse_expr_from_str(&s).unwrap();
+            assert_eq!(evaluate(&expr, &parameters), Ok(expected));
+        }
+
+        // right-leaning, unlike parsed chains
+        let mut expr = Expr::Value(Value::from(0));
+        for _ in 0..EVALUATE_DEPTH {
+            expr = Expr::Op(
+                Box::new(Expr::Identifier("n".to_owned())),
+                Op::Additive(AdditiveOp::Add),
+                Box::new(expr),
+            );
+        }
+        assert_eq!(
+            evaluate(&expr, &parameters),
+            Ok(Value::from(EVALUATE_DEPTH as f64))
+        );
+
+        // the innermost branch fails
+        let branches = "if {a} then 0 else ".repeat(EVALUATE_DEPTH) + "1 + {s}";
+        let expr = parser::parse_expr_from_str(&branches).unwrap();
+        let error = evaluate(&expr, &parameters).unwrap_err();
+        assert_eq!(error.path.0.len(), EVALUATE_DEPTH);
+        assert!(error.path.0.iter().all(|step| *step == NodeStep::Else));
+    }
+
+    #[test]
+    fn test_located_expr() {
+        let parameters = HashMap::from([
+            ("a", Value::from(1)),
+            ("s", Value::from("x")),
+            ("user", Value::from(&serde_json::json!({ "tags": [] }))),
+        ]);
+        let test_cases: Vec<(&str, EvaluatorError, &str)> = vec![
+            (
+                "{a} + {s}",
+                EvaluatorError::InvalidOperation(
+                    Value::from(1),
+                    Op::Additive(AdditiveOp::Add),
+                    Value::from("x"),
+                ),
+                "/",
+            ),
+            (
+                "{a} > 0 && -{s} == 1",
+                EvaluatorError::InvalidUnaryOperation(UnaryOp::Neg, Value::from("x")),
+                "/rhs/lhs",
+            ),
+            (
+                "max({a}, {b}) > 1",
+                EvaluatorError::InvalidParameter("b".to_owned()),
+                "/lhs/args/1",
+            ),
+            (
+                "if {a} == 1 then {user.tags[0]} else 0",
+                EvaluatorError::InvalidPath("user.tags[0]".to_owned(), PathSegment::Index(0)),
+                "/then",
+            ),
+            (
+                "if {s} then 1 else 0",
+                EvaluatorError::InvalidCondition(Value::from("x")),
+                "/",
+            ),
+            (
+                "1 + len({a}, 2)",
+                EvaluatorError::InvalidArgumentCount("len".to_owned(), Arity::Exact(1), 2),
+                "/rhs",
+            ),
+        ];
+        for (expr, error, path) in test_cases {
+            let parsed = parser::parse_expr_from_str(expr).unwrap();
+            let output = super::evaluate(&parsed, &parameters).unwrap_err();
+            assert_eq!(output.error, error, "expr: {}", expr);
+            assert_eq!(output.path.to_string(), path, "expr: {}", expr);
+        }
+
+        let json = serde_json::json!({
+            "lhs": { "lhs": "{a}", "op": ">", "rhs": 0 },
+            "op": "&&",
+            "rhs": { "call": "upper", "args": ["{a}"] },
+        });
+        let expr = parser::parse_expr_from_json(&json.to_string()).unwrap();
+        let output = super::evaluate(&expr, &parameters).unwrap_err();
+        assert_eq!(output.path.to_string(), "/rhs");
+        assert_eq!(json.pointer("/rhs/call"), Some(&serde_json::json!("upper")));
+        assert_eq!(
+            output.to_string(),
+            "invalid argument 1 for function upper at /rhs"
+        );
+    }
+
+    #[test]
+    fn test_call_expr() {
+        let parameters = HashMap::from([
+            ("name", Value::from("Alice")),
+            ("scores", Value::from(&serde_json::json!([3, 9, 4]))),
+        ]);
+        let test_cases: Vec<TestCase> = vec![
+            TestCase {
+                expr: "len({name})",
+                want: Ok(Value::from(5)),
+            },
+            TestCase {
+                expr: "len({scores}) == 3",
+                want: Ok(Value::from(true)),
+            },
+            TestCase {
+                expr: "lower({name}) == lower(upper({name}))",
+                want: Ok(Value::from(true)),
+            },
+            TestCase {
+                expr: "upper({name})",
+                want: Ok(Value::from("ALICE")),
+            },
+            TestCase {
+                expr: "abs(-2.5)",
+                want: Ok(Value::from(2.5)),
+            },
+            TestCase {
+                expr: "floor(2.7) + ceil(2.2)",
+                want: Ok(Value::from(5)),
+            },
+            TestCase {
+                expr: "round(2.5)",
+                want: Ok(Value::from(3)),
+            },
+            TestCase {
+                expr: "round(1.23456, 2)",
+                want: Ok(Value::from(1.23)),
+            },
+            TestCase {
+                expr: "round(1234.5, -2)",
+                want: Ok(Value::from(1200)),
+            },
+            TestCase {
+                expr: "round(1.5, 400)",
+                want: Ok(Value::from(1.5)),
+            },
+            TestCase {
+                expr: "round(1.5, 300)",
+                want: Ok(Value::from(1.5)),
+            },
+            TestCase {
+                expr: "round(1.5, -400)",
+                want: Ok(Value::from(0)),
+            },
+            TestCase {
+                expr: "round(0, 400) + round(-1.5, -400)",
+                want: Ok(Value::from(0)),
+            },
+            TestCase {
+                expr: "min(3, 1, 2)",
+                want: Ok(Value::from(1)),
+            },
+            TestCase {
+                expr: "max({scores})",
+                want: Ok(Value::from(9)),
+            },
+            TestCase {
+                expr: "max(1, min(5, 7)) * 2",
+                want: Ok(Value::from(10)),
+            },
+            TestCase {
+                expr: "contains({scores}, 9)",
+                want: Ok(Value::from(true)),
+            },
+            TestCase {
+                expr: "contains({name}, {name})",
+                want: Ok(Value::from(true)),
+            },
+            TestCase {
+                expr: "starts_with({name}, {name}) && ends_with({name}, {name})",
+                want: Ok(Value::from(true)),
+            },
+            TestCase {
+                expr: "unknown(1)",
+                want: Err(EvaluatorError::UnknownFunction("unknown".to_owned())),
+            },
+            TestCase {
+                expr: "len()",
+                want: Err(EvaluatorError::InvalidArgumentCount(
+                    "len".to_owned(),
+                    Arity::Exact(1),
+                    0,
+                )),
+            },
+            TestCase {
+                expr: "round(1, 2, 3)",
+                want: Err(EvaluatorError::InvalidArgumentCount(
+                    "round".to_owned(),
+                    Arity::Range(1, 2),
+                    3,
+                )),
+            },
+            TestCase {
+                expr: "len(1)",
+                want: Err(EvaluatorError::InvalidArgument(
+                    "len".to_owned(),
+                    Value::from(1),
+                )),
+            },
+            TestCase {
+                expr: "max(1, true)",
+                want: Err(EvaluatorError::InvalidArgument(
+                    "max".to_owned(),
+                    Value::from(true),
+                )),
+            },
+            TestCase {
+                expr: "min([])",
+                want: Err(EvaluatorError::InvalidArgument(
+                    "min".to_owned(),
+                    Value::Array(vec![]),
+                )),
+            },
+        ];
+        test_cases.iter().for_each(|case| {
+            let expr = parser::parse_expr_from_str(case.expr).unwrap();
+            let output = super::evaluate(&expr, &parameters).map_err(|e| e.error);
+            assert_eq!(case.want, output, "expr: {}", case.expr);
+        });
+    }
+
+    #[derive(Debug)]
+    struct RiskError(String);
+
+    impl std::fmt::Display for RiskError {
+        fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+            write!(f, "no risk data for {}", self.0)
+        }
+    }
+
+    impl std::error::Error for RiskError {}
+
+    #[test]
+    fn test_custom_function_expr() {
+        let evaluator = Evaluator::new()
+            .with_function(
+                "risk_score",
+                Signature::new(vec![ValueType::String]),
+                |args| match &args[0] {
+                    Value::String(id) if id == "u1" => Ok(Value::from(0.9)),
+                    Value::String(id) => Err(RiskError(id.clone())),
+                    _ => unreachable!(),
+                },
+            )
+            .with_function(
+                "sum",
+                Signature::variadic(vec![], ValueType::Number),
+                |args| -> Result<Value, EvaluatorError> {
+                    Ok(Value::Number(
+                        args.iter()
+                            .map(|v| match v {
+                                Value::Number(n) => *n,
+                                _ => 0.0,
+                            })
+                            .sum(),
+                    ))
+                },
+            )
+            .with_function("len", Signature::new(vec![ValueType::Any]), |_| {
+                Ok::<_, String>(Value::from(-1))
+            });
+
+        let parameters = HashMap::from([("user_id", Value::from("u1"))]);
+        let test_cases: Vec<TestCase> = vec![
+            TestCase {
+                expr: "risk_score({user_id}) > 0.5",
+                want: Ok(Value::from(true)),
+            },
+            TestCase {
+                expr: "sum(1, 2, abs(-3))",
+                want: Ok(Value::from(6)),
+            },
+            TestCase {
+                expr: "sum()",
+                want: Ok(Value::from(0)),
+            },
+            TestCase {
+                expr: "len('abc')",
+                want: Ok(Value::from(-1)),
+            },
+            TestCase {
+                expr: "risk_score()",
+                want: Err(EvaluatorError::InvalidArgumentCount(
+                    "risk_score".to_owned(),
+                    Arity::Exact(1),
+                    0,
+                )),
+            },
+            TestCase {
+                expr: "risk_score(1)",
+                want: Err(EvaluatorError::InvalidArgument(
+                    "risk_score".to_owned(),
+                    Value::from(1),
+                )),
+            },
+            TestCase {
+                expr: "sum(1, true)",
+                want: Err(EvaluatorError::InvalidArgument(
+                    "sum".to_owned(),
+                    Value::from(true),
+                )),
+            },
+        ];
+        test_cases.iter().for_each(|case| {
+            let expr = parser::parse_expr_from_str(case.expr).unwrap();
+            let output = evaluator.evaluate(&expr, &parameters).map_err(|e| e.error);
             assert_eq!(case.want, output, "expr: {}", case.expr);
         });
+
+        let expr = parser::parse_expr_from_str("risk_score({user_id})").unwrap();
+        let parameters = HashMap::from([("user_id", Value::from("u2"))]);
+        match evaluator.evaluate(&expr, &parameters).map_err(|e| e.error) {
+            Err(EvaluatorError::FunctionFailed(name, e)) => {
+                assert_eq!(name, "risk_score");
+                assert_eq!(e.to_string(), "no risk data for u2");
+                assert_eq!(e.downcast_ref::<RiskError>().unwrap().0, "u2");
+            }
+            output => panic!("unexpected output {:?}", output),
+        }
+
+        // the free function only knows the built-in functions
+        assert_eq!(
+            super::evaluate(&expr, &parameters),
+            Err(EvaluatorError::UnknownFunction("risk_score".to_owned()).into())
+        );
     }
 
     #[test]
@@ -798,10 +2455,18 @@ mod tests {
                 expr: "(1 + 2) * 3",
                 want: Ok(Value::from(9)),
             },
+            TestCase {
+                expr: "!false && 2 > 1",
+                want: Ok(Value::from(true)),
+            },
+            TestCase {
+                expr: "-2 * 3 + 1",
+                want: Ok(Value::from(-5)),
+            },
         ];
         test_cases.iter().for_each(|case| {
             let expr = parser::parse_expr_from_str(case.expr).unwrap();
-            let output = super::evaluate(&expr, &empty_parameters);
+            let output = super::evaluate(&expr, &empty_parameters).map_err(|e| e.error);
             assert_eq!(case.want, output, "expr: {}", case.expr);
         });
     }
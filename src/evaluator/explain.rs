@@ -0,0 +1,551 @@
+use serde::ser::{Serialize, SerializeMap, Serializer};
+use std::fmt::{self, Display, Formatter};
+
+use crate::ast::{
+    expr::{Expr, NodePath, NodeStep},
+    op::Op,
+    value::Value,
+};
+use crate::evaluator::context::Context;
+use crate::evaluator::evaluator::{
+    evaluate_binary_expr, evaluate_leaf, evaluate_unary_expr, short_circuit,
+    with_default_evaluator, Callee, Evaluator, EvaluatorError, LocatedError,
+};
+use crate::evaluator::limits::Budget;
+
+/// Result of an evaluation along with the trace of every subexpression
+#[derive(Debug, PartialEq)]
+pub struct Explanation {
+    pub result: Result<Value, LocatedError>,
+    pub trace: Trace,
+}
+
+/// Outcome of one subexpression in a `Trace`
+#[derive(Debug, Clone, PartialEq)]
+pub enum Outcome {
+    Value(Value),
+    /// Evaluation failed in this subexpression
+    Error(LocatedError),
+    /// Evaluation failed in the operand reached by the step, or in one of its operands
+    Failed(NodeStep),
+    /// Not evaluated, because of short-circuiting, a branch not taken or an earlier error
+    Skipped,
+}
+
+impl Display for Outcome {
+    fn fmt(&self, fmt: &mut Formatter) -> fmt::Result {
+        match self {
+            Outcome::Value(v) => write!(fmt, "{}", v),
+            Outcome::Error(e) => write!(fmt, "error: {}", e.error),
+            Outcome::Failed(step) => write!(fmt, "error in {}", step),
+            Outcome::Skipped => write!(fmt, "skipped"),
+        }
+    }
+}
+
+/// Tree mirroring an expression with the outcome of each subexpression.
+///
+/// Each subexpression is labelled with its own text only: the operator of an operation, the
+/// name of a called function, `if` for a conditional, or the whole identifier, path or value.
+/// Displays as indented text, one subexpression per line, and serializes to JSON as nested
+/// objects with `step`, `node`, one of `value`, `error` and `path`, `failed` or `skipped`, and
+/// `operands`. Skipped subexpressions have no operands.
+///
+/// Displaying and dropping a trace do not recurse; serializing one does, so bound the depth of
+/// expressions whose traces are serialized with `Limits::with_max_depth`.
+///
+/// # Examples
+///
+/// ```
+/// use evaluator_rs::*;
+/// use std::collections::HashMap;
+///
+/// let expr = parse_expr_from_str("{age} >= 18 && {country} == 'FR'").unwrap();
+/// let parameters = HashMap::from([("age", Value::from(16)), ("country", Value::from("FR"))]);
+/// let explanation = explain(&expr, &parameters);
+/// assert_eq!(explanation.result, Ok(Value::from(false)));
+/// assert_eq!(
+///     explanation.trace.to_string(),
+///     "&& => false
+///   lhs: >= => false
+///     lhs: {age} => 16
+///     rhs: 18 => 18
+///   rhs: == => skipped
+/// "
+/// );
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct Trace {
+    /// Step from the parent expression, `None` at the root
+    pub step: Option<NodeStep>,
+    pub node: String,
+    pub outcome: Outcome,
+    pub operands: Vec<Trace>,
+}
+
+impl Trace {
+    fn skipped(step: NodeStep, expr: &Expr) -> Self {
+        Trace {
+            step: Some(step),
+            node: label(expr),
+            outcome: Outcome::Skipped,
+            operands: vec![],
+        }
+    }
+}
+
+/// Drop operands with an explicit stack, as traces are as deep as the expression they explain
+impl Drop for Trace {
+    fn drop(&mut self) {
+        let mut stack = std::mem::take(&mut self.operands);
+        while let Some(mut trace) = stack.pop() {
+            stack.append(&mut trace.operands);
+        }
+    }
+}
+
+/// Written with an explicit stack, so traces nested arbitrarily deep can be displayed
+impl Display for Trace {
+    fn fmt(&self, fmt: &mut Formatter) -> fmt::Result {
+        let mut stack = vec![(self, 0)];
+        while let Some((trace, indent)) = stack.pop() {
+            write!(fmt, "{:width$}", "", width = indent * 2)?;
+            if let Some(step) = trace.step {
+                write!(fmt, "{}: ", step)?;
+            }
+            writeln!(fmt, "{} => {}", trace.node, trace.outcome)?;
+            stack.extend(
+                trace
+                    .operands
+                    .iter()
+                    .rev()
+                    .map(|operand| (operand, indent + 1)),
+            );
+        }
+        Ok(())
+    }
+}
+
+impl Serialize for Trace {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(None)?;
+        if let Some(step) = self.step {
+            map.serialize_entry("step", &step.to_string())?;
+        }
+        map.serialize_entry("node", &self.node)?;
+        match &self.outcome {
+            Outcome::Value(v) => map.serialize_entry("value", v)?,
+            Outcome::Error(e) => {
+                map.serialize_entry("error", &e.error.to_string())?;
+                map.serialize_entry("path", &e.path.to_string())?;
+            }
+            Outcome::Failed(step) => map.serialize_entry("failed", &step.to_string())?,
+            Outcome::Skipped => map.serialize_entry("skipped", &true)?,
+        }
+        if !self.operands.is_empty() {
+            map.serialize_entry("operands", &self.operands)?;
+        }
+        map.end()
+    }
+}
+
+impl Evaluator {
+    /// evaluate expression with parameters, tracing the outcome of every subexpression.
+    ///
+    /// The result is the one `evaluate` returns. Traces are built with an explicit work stack,
+    /// so expressions of any depth can be explained within the limits of the evaluator.
+    pub fn explain<C: Context + ?Sized>(&self, expr: &Expr, parameters: &C) -> Explanation {
+        let tracer = Tracer {
+            evaluator: self,
+            parameters,
+            budget: Budget::new(self.limits()),
+            frames: vec![],
+        };
+        tracer.explain(expr)
+    }
+}
+
+/// Text of `expr` without its operands
+fn label(expr: &Expr) -> String {
+    match expr {
+        Expr::Op(_, op, _) => op.to_string(),
+        Expr::Unary(op, _) => op.to_string(),
+        Expr::Call(name, _) => format!("{}()", name),
+        Expr::Conditional(_, _, _) => "if".to_owned(),
+        Expr::Identifier(_) | Expr::Path(_, _) | Expr::Value(_) => expr.to_string(),
+    }
+}
+
+/// Operand `i` of `expr`, in the order of the trace, along with the step to it
+fn operand(expr: &Expr, i: usize) -> Option<(NodeStep, &Expr)> {
+    match (expr, i) {
+        (Expr::Op(lhs, _, _), 0) => Some((NodeStep::Lhs, lhs)),
+        (Expr::Op(_, _, rhs), 1) | (Expr::Unary(_, rhs), 0) => Some((NodeStep::Rhs, rhs)),
+        (Expr::Call(_, args), i) => args.get(i).map(|arg| (NodeStep::Arg(i), arg)),
+        (Expr::Conditional(cond, _, _), 0) => Some((NodeStep::If, cond)),
+        (Expr::Conditional(_, then, _), 1) => Some((NodeStep::Then, then)),
+        (Expr::Conditional(_, _, otherwise), 2) => Some((NodeStep::Else, otherwise)),
+        _ => None,
+    }
+}
+
+/// evaluate expression with parameters, tracing the outcome of every subexpression
+pub fn explain<C: Context + ?Sized>(expr: &Expr, parameters: &C) -> Explanation {
+    with_default_evaluator(|evaluator| evaluator.explain(expr, parameters))
+}
+
+struct Tracer<'e, C: ?Sized> {
+    evaluator: &'e Evaluator,
+    parameters: &'e C,
+    budget: Budget,
+    /// Subexpressions being traced, from the root down to the current one
+    frames: Vec<Frame<'e>>,
+}
+
+/// Subexpression being traced, with the values and traces of its operands so far
+struct Frame<'e> {
+    step: Option<NodeStep>,
+    expr: &'e Expr,
+    /// Function resolved for a call
+    callee: Option<Callee<'e>>,
+    values: Vec<Value>,
+    operands: Vec<Trace>,
+}
+
+impl Frame<'_> {
+    fn trace(self, outcome: Outcome) -> Trace {
+        Trace {
+            step: self.step,
+            node: label(self.expr),
+            outcome,
+            operands: self.operands,
+        }
+    }
+}
+
+/// What a subexpression needs next to be traced
+enum Next<'e> {
+    /// Trace the operand reached by the step
+    Operand(NodeStep, &'e Expr),
+    /// The outcome of the subexpression is known
+    Done(Result<Value, EvaluatorError>),
+}
+
+impl<'e, C: Context + ?Sized> Tracer<'e, C> {
+    fn explain(mut self, expr: &'e Expr) -> Explanation {
+        let mut entered = self.enter(None, expr);
+        loop {
+            let rs = match entered {
+                Ok(()) => match self.next() {
+                    Next::Operand(step, operand) => {
+                        entered = self.enter(Some(step), operand);
+                        continue;
+                    }
+                    Next::Done(rs) => rs,
+                },
+                Err(e) => Err(e),
+            };
+            entered = Ok(());
+
+            let frame = self.frames.pop().expect("subexpression entered");
+            let value = match rs {
+                Ok(v) => v,
+                Err(error) => return self.fail(frame, error),
+            };
+            let trace = frame.trace(Outcome::Value(value.clone()));
+            match self.frames.last_mut() {
+                Some(parent) => {
+                    parent.operands.push(trace);
+                    parent.values.push(value);
+                }
+                None => {
+                    return Explanation {
+                        result: Ok(value),
+                        trace,
+                    }
+                }
+            }
+        }
+    }
+
+    /// Start tracing the subexpression reached by `step`, one deeper than the current one
+    fn enter(&mut self, step: Option<NodeStep>, expr: &'e Expr) -> Result<(), EvaluatorError> {
+        self.frames.push(Frame {
+            step,
+            expr,
+            callee: None,
+            values: vec![],
+            operands: vec![],
+        });
+        self.budget.step(self.frames.len())?;
+        if let Expr::Call(name, args) = expr {
+            // unknown functions and wrong argument counts fail before the arguments are
+            // evaluated
+            let callee = self.evaluator.callee(name, args.len())?;
+            self.frames
+                .last_mut()
+                .expect("subexpression entered")
+                .callee = Some(callee);
+        }
+        Ok(())
+    }
+
+    /// Evaluate the current subexpression from the values of its operands so far, or pick the
+    /// operand to trace next
+    fn next(&mut self) -> Next<'e> {
+        use NodeStep::*;
+
+        let frame = self.frames.last_mut().expect("subexpression entered");
+        let rs = match (frame.expr, frame.values.as_slice()) {
+            (Expr::Identifier(_) | Expr::Path(_, _) | Expr::Value(_), _) => {
+                evaluate_leaf(frame.expr, self.parameters)
+            }
+            (Expr::Op(lhs, _, _), []) => return Next::Operand(Lhs, lhs),
+            (Expr::Op(_, Op::Logical(o), rhs), [lr]) => match short_circuit(lr, o) {
+                Some(v) => {
+                    frame.operands.push(Trace::skipped(Rhs, rhs));
+                    Ok(v)
+                }
+                None => return Next::Operand(Rhs, rhs),
+            },
+            (Expr::Op(_, _, rhs), [_]) => return Next::Operand(Rhs, rhs),
+            (Expr::Op(_, op, _), [lr, rr]) => {
+                evaluate_binary_expr(lr, op, rr, self.evaluator.regexes())
+            }
+            (Expr::Unary(_, rhs), []) => return Next::Operand(Rhs, rhs),
+            (Expr::Unary(op, _), [rr]) => evaluate_unary_expr(op, rr),
+            (Expr::Call(name, args), values) => match args.get(values.len()) {
+                Some(arg) => return Next::Operand(Arg(values.len()), arg),
+                None => frame
+                    .callee
+                    .as_ref()
+                    .expect("callee resolved")
+                    .call(name, values),
+            },
+            (Expr::Conditional(cond, _, _), []) => return Next::Operand(If, cond),
+            (Expr::Conditional(_, then, _), [Value::Bool(true)]) => {
+                return Next::Operand(Then, then)
+            }
+            (Expr::Conditional(_, then, otherwise), [Value::Bool(false)]) => {
+                frame.operands.push(Trace::skipped(Then, then));
+                return Next::Operand(Else, otherwise);
+            }
+            (Expr::Conditional(_, _, _), [v]) => Err(EvaluatorError::InvalidCondition(v.clone())),
+            (Expr::Conditional(_, _, otherwise), [Value::Bool(true), v]) => {
+                frame.operands.push(Trace::skipped(Else, otherwise));
+                Ok(v.clone())
+            }
+            (Expr::Conditional(_, _, _), [_, v]) => Ok(v.clone()),
+            _ => unreachable!("operands evaluated"),
+        };
+        Next::Done(rs)
+    }
+
+    /// Locate the error of `frame`, the innermost subexpression, and fail every subexpression
+    /// up to the root, skipping the operands not traced yet
+    fn fail(mut self, mut frame: Frame<'e>, error: EvaluatorError) -> Explanation {
+        let steps = self.frames.iter().chain([&frame]).filter_map(|f| f.step);
+        let error = LocatedError {
+            error,
+            path: NodePath(steps.collect()),
+        };
+
+        let mut outcome = Outcome::Error(error.clone());
+        loop {
+            let step = frame.step;
+            let mut i = frame.operands.len();
+            while let Some((step, operand)) = operand(frame.expr, i) {
+                frame.operands.push(Trace::skipped(step, operand));
+                i += 1;
+            }
+            let trace = frame.trace(outcome);
+            match self.frames.pop() {
+                Some(parent) => {
+                    frame = parent;
+                    frame.operands.push(trace);
+                    outcome = Outcome::Failed(step.expect("operand step"));
+                }
+                None => {
+                    return Explanation {
+                        result: Err(error),
+                        trace,
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::op::UnaryOp;
+    use crate::evaluator::evaluator::evaluate;
+    use crate::evaluator::limits::Limits;
+    use crate::parser::parser;
+    use crate::test_util::{chain, deep_exprs, deep_parameters, PASS_DEPTH};
+    use std::collections::HashMap;
+
+    fn parameters() -> HashMap<&'static str, Value> {
+        HashMap::from([
+            ("a", Value::from(2)),
+            ("b", Value::from(false)),
+            ("s", Value::from("x")),
+        ])
+    }
+
+    #[test]
+    fn test_explain() {
+        let test_cases: Vec<(&str, &str)> = vec![
+            (
+                "{b} || {a} > 1",
+                "|| => true
+  lhs: {b} => false
+  rhs: > => true
+    lhs: {a} => 2
+    rhs: 1 => 1
+",
+            ),
+            (
+                "if {a} > 1 then 'big' else 'small'",
+                "if => 'big'
+  if: > => true
+    lhs: {a} => 2
+    rhs: 1 => 1
+  then: 'big' => 'big'
+  else: 'small' => skipped
+",
+            ),
+            (
+                "max({a}, -{s}, 3) + 1",
+                "+ => error in lhs
+  lhs: max() => error in args/1
+    args/0: {a} => 2
+    args/1: - => error: invalid operation -'x'
+      rhs: {s} => 'x'
+    args/2: 3 => skipped
+  rhs: 1 => skipped
+",
+            ),
+            (
+                "len({a}, 1)",
+                "len() => error: function len expects 1 arguments, got 2
+  args/0: {a} => skipped
+  args/1: 1 => skipped
+",
+            ),
+        ];
+        test_cases.into_iter().for_each(|(s, want)| {
+            let expr = parser::parse_expr_from_str(s).unwrap();
+            let explanation = explain(&expr, &parameters());
+            assert_eq!(explanation.trace.to_string(), want, "expr: {}", s);
+        });
+
+        let expr = parser::parse_expr_from_str("max({a}, -{s}, 3) + 1").unwrap();
+        let explanation = explain(&expr, &parameters());
+        let error = LocatedError {
+            error: EvaluatorError::InvalidUnaryOperation(UnaryOp::Neg, Value::from("x")),
+            path: NodePath(vec![NodeStep::Lhs, NodeStep::Arg(1)]),
+        };
+        assert_eq!(explanation.result, Err(error.clone()));
+        assert_eq!(explanation.trace.outcome, Outcome::Failed(NodeStep::Lhs));
+        assert_eq!(
+            explanation.trace.operands[0].operands[1].outcome,
+            Outcome::Error(error)
+        );
+    }
+
+    #[test]
+    fn test_explain_json() {
+        let expr = parser::parse_expr_from_str("{b} && {a} > 1").unwrap();
+        let explanation = explain(&expr, &parameters());
+        assert_eq!(
+            serde_json::to_value(&explanation.trace).unwrap(),
+            serde_json::json!({
+                "node": "&&",
+                "value": false,
+                "operands": [
+                    { "step": "lhs", "node": "{b}", "value": false },
+                    { "step": "rhs", "node": ">", "skipped": true },
+                ],
+            })
+        );
+
+        let expr = parser::parse_expr_from_str("-{s} > 1").unwrap();
+        let explanation = explain(&expr, &parameters());
+        assert_eq!(
+            serde_json::to_value(&explanation.trace).unwrap(),
+            serde_json::json!({
+                "node": ">",
+                "failed": "lhs",
+                "operands": [
+                    {
+                        "step": "lhs",
+                        "node": "-",
+                        "error": "invalid operation -'x'",
+                        "path": "/lhs",
+                        "operands": [{ "step": "rhs", "node": "{s}", "value": "x" }],
+                    },
+                    { "step": "rhs", "node": "1", "skipped": true },
+                ],
+            })
+        );
+    }
+
+    #[test]
+    fn test_explain_evaluates_identically() {
+        let exprs = vec![
+            "{a} + 3 * 2 - -1",
+            "{a} / 0",
+            "{a} % 2 == 0 && !{b}",
+            "{b} && {missing}",
+            "if {s} then 1 else 2",
+            "if {b} then {missing} else {a}",
+            "{s} matches '('",
+            "upper({s}) in ['X', 'Y']",
+            "unknown({a})",
+            "{a} + {s}",
+        ];
+        exprs.iter().for_each(|s| {
+            let expr = parser::parse_expr_from_str(s).unwrap();
+            assert_eq!(
+                explain(&expr, &parameters()).result,
+                evaluate(&expr, &parameters()),
+                "expr: {}",
+                s
+            );
+        });
+    }
+
+    #[test]
+    fn test_explain_deep_expr() {
+        for (s, expected) in deep_exprs(PASS_DEPTH) {
+            let expr = parser::parse_expr_from_str(&s).unwrap();
+            let explanation = explain(&expr, &deep_parameters());
+            assert_eq!(explanation.result, Ok(expected.clone()));
+            assert_eq!(explanation.trace.outcome, Outcome::Value(expected));
+        }
+
+        let expr = parser::parse_expr_from_str(&chain("{x}", PASS_DEPTH)).unwrap();
+        let explanation = explain(&expr, &parameters());
+        assert_eq!(
+            explanation.result,
+            Err(LocatedError {
+                error: EvaluatorError::InvalidParameter("x".to_owned()),
+                path: NodePath(vec![NodeStep::Lhs; PASS_DEPTH - 1]),
+            })
+        );
+        assert_eq!(explanation.trace.outcome, Outcome::Failed(NodeStep::Lhs));
+
+        let expr = parser::parse_expr_from_str(&chain("{b}", 300)).unwrap();
+        assert_eq!(explain(&expr, &parameters()).result, Ok(Value::from(false)));
+        let evaluator = Evaluator::new().with_limits(Limits::new().with_max_depth(100));
+        assert_eq!(
+            evaluator
+                .explain(&expr, &parameters())
+                .result
+                .map_err(|e| e.error),
+            Err(EvaluatorError::DepthLimitExceeded(100))
+        );
+    }
+}
@@ -0,0 +1,417 @@
+use std::fmt::{Debug, Display, Error, Formatter};
+use std::sync::Arc;
+
+use crate::ast::value::{Value, ValueType};
+use crate::evaluator::evaluator::EvaluatorError;
+
+/// Number of arguments accepted by a function.
+#[derive(Copy, Debug, Clone, PartialEq)]
+pub enum Arity {
+    Exact(usize),
+    Range(usize, usize),
+    AtLeast(usize),
+}
+
+impl Arity {
+    pub fn accepts(&self, count: usize) -> bool {
+        match *self {
+            Arity::Exact(n) => count == n,
+            Arity::Range(min, max) => count >= min && count <= max,
+            Arity::AtLeast(min) => count >= min,
+        }
+    }
+}
+
+impl Display for Arity {
+    fn fmt(&self, fmt: &mut Formatter) -> Result<(), Error> {
+        match *self {
+            Arity::Exact(n) => write!(fmt, "{}", n),
+            Arity::Range(min, max) => write!(fmt, "{} to {}", min, max),
+            Arity::AtLeast(min) => write!(fmt, "at least {}", min),
+        }
+    }
+}
+
+/// Parameter types of a host function; the last type may repeat.
+///
+/// # Examples
+///
+/// ```
+/// use evaluator_rs::*;
+///
+/// let signature = Signature::variadic(vec![ValueType::String], ValueType::Number);
+/// assert_eq!(signature.arity(), Arity::AtLeast(1));
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct Signature {
+    params: Vec<ValueType>,
+    rest: Option<ValueType>,
+}
+
+impl Signature {
+    pub fn new(params: Vec<ValueType>) -> Self {
+        Signature { params, rest: None }
+    }
+
+    pub fn variadic(params: Vec<ValueType>, rest: ValueType) -> Self {
+        Signature {
+            params,
+            rest: Some(rest),
+        }
+    }
+
+    pub fn arity(&self) -> Arity {
+        match self.rest {
+            None => Arity::Exact(self.params.len()),
+            Some(_) => Arity::AtLeast(self.params.len()),
+        }
+    }
+
+    /// Expected type of the argument at `index`
+    pub fn param(&self, index: usize) -> Option<ValueType> {
+        self.params.get(index).copied().or(self.rest)
+    }
+}
+
+/// Error returned by a host function, kept as the original error value.
+#[derive(Clone)]
+pub struct FunctionError(Arc<dyn std::error::Error + Send + Sync>);
+
+impl FunctionError {
+    pub fn new<E>(error: E) -> Self
+    where
+        E: Into<Box<dyn std::error::Error + Send + Sync>>,
+    {
+        FunctionError(Arc::from(error.into()))
+    }
+
+    /// Borrow the original error as `E`, if it has that type
+    pub fn downcast_ref<E: std::error::Error + 'static>(&self) -> Option<&E> {
+        self.0.downcast_ref::<E>()
+    }
+}
+
+impl Debug for FunctionError {
+    fn fmt(&self, fmt: &mut Formatter) -> Result<(), Error> {
+        Debug::fmt(&self.0, fmt)
+    }
+}
+
+impl Display for FunctionError {
+    fn fmt(&self, fmt: &mut Formatter) -> Result<(), Error> {
+        Display::fmt(&self.0, fmt)
+    }
+}
+
+impl std::error::Error for FunctionError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        self.0.source()
+    }
+}
+
+impl PartialEq for FunctionError {
+    fn eq(&self, other: &Self) -> bool {
+        self.to_string() == other.to_string()
+    }
+}
+
+type HostFn = dyn Fn(&[Value]) -> Result<Value, FunctionError> + Send + Sync;
+
+/// Function registered by the host on an `Evaluator`.
+#[derive(Clone)]
+pub(crate) struct Function {
+    pub signature: Signature,
+    pub func: Arc<HostFn>,
+}
+
+/// Built-in function. `params` lists the types accepted by each parameter, the last entry
+/// applying to any further argument; each argument is checked on its own, so the function
+/// itself may still reject some combinations.
+pub(crate) struct Builtin {
+    pub name: &'static str,
+    pub arity: Arity,
+    pub params: &'static [&'static [ValueType]],
+    pub returns: ValueType,
+    pub func: fn(&str, &[Value]) -> Result<Value, EvaluatorError>,
+}
+
+impl Builtin {
+    /// Types accepted by the argument at `index`
+    pub fn param(&self, index: usize) -> &'static [ValueType] {
+        self.params
+            .get(index)
+            .or(self.params.last())
+            .copied()
+            .unwrap_or_default()
+    }
+}
+
+const NUMBER: &[ValueType] = &[ValueType::Number];
+const STRING: &[ValueType] = &[ValueType::String];
+const ANY: &[ValueType] = &[ValueType::Any];
+/// Values with a length or members
+const COLLECTION: &[ValueType] = &[ValueType::String, ValueType::Array, ValueType::Object];
+/// Numbers, or an array of numbers
+const NUMBERS: &[ValueType] = &[ValueType::Number, ValueType::Array];
+
+static BUILTINS: &[Builtin] = &[
+    Builtin {
+        name: "len",
+        arity: Arity::Exact(1),
+        params: &[COLLECTION],
+        returns: ValueType::Number,
+        func: len,
+    },
+    Builtin {
+        name: "lower",
+        arity: Arity::Exact(1),
+        params: &[STRING],
+        returns: ValueType::String,
+        func: lower,
+    },
+    Builtin {
+        name: "upper",
+        arity: Arity::Exact(1),
+        params: &[STRING],
+        returns: ValueType::String,
+        func: upper,
+    },
+    Builtin {
+        name: "trim",
+        arity: Arity::Exact(1),
+        params: &[STRING],
+        returns: ValueType::String,
+        func: trim,
+    },
+    Builtin {
+        name: "abs",
+        arity: Arity::Exact(1),
+        params: &[NUMBER],
+        returns: ValueType::Number,
+        func: abs,
+    },
+    Builtin {
+        name: "floor",
+        arity: Arity::Exact(1),
+        params: &[NUMBER],
+        returns: ValueType::Number,
+        func: floor,
+    },
+    Builtin {
+        name: "ceil",
+        arity: Arity::Exact(1),
+        params: &[NUMBER],
+        returns: ValueType::Number,
+        func: ceil,
+    },
+    Builtin {
+        name: "round",
+        arity: Arity::Range(1, 2),
+        params: &[NUMBER],
+        returns: ValueType::Number,
+        func: round,
+    },
+    Builtin {
+        name: "min",
+        arity: Arity::AtLeast(1),
+        params: &[NUMBERS],
+        returns: ValueType::Number,
+        func: min,
+    },
+    Builtin {
+        name: "max",
+        arity: Arity::AtLeast(1),
+        params: &[NUMBERS],
+        returns: ValueType::Number,
+        func: max,
+    },
+    Builtin {
+        name: "contains",
+        arity: Arity::Exact(2),
+        params: &[COLLECTION, ANY],
+        returns: ValueType::Bool,
+        func: contains,
+    },
+    Builtin {
+        name: "starts_with",
+        arity: Arity::Exact(2),
+        params: &[STRING],
+        returns: ValueType::Bool,
+        func: starts_with,
+    },
+    Builtin {
+        name: "ends_with",
+        arity: Arity::Exact(2),
+        params: &[STRING],
+        returns: ValueType::Bool,
+        func: ends_with,
+    },
+];
+
+/// Look up a built-in function by name
+pub(crate) fn builtin(name: &str) -> Option<&'static Builtin> {
+    BUILTINS.iter().find(|b| b.name == name)
+}
+
+fn invalid_argument(name: &str, arg: &Value) -> EvaluatorError {
+    EvaluatorError::InvalidArgument(name.to_owned(), arg.clone())
+}
+
+fn number(name: &str, arg: &Value) -> Result<f64, EvaluatorError> {
+    match arg {
+        Value::Number(v) => Ok(*v),
+        v => Err(invalid_argument(name, v)),
+    }
+}
+
+fn string<'a>(name: &str, arg: &'a Value) -> Result<&'a str, EvaluatorError> {
+    match arg {
+        Value::String(v) => Ok(v),
+        v => Err(invalid_argument(name, v)),
+    }
+}
+
+fn len(name: &str, args: &[Value]) -> Result<Value, EvaluatorError> {
+    match &args[0] {
+        Value::String(v) => Ok(Value::from(v.chars().count() as f64)),
+        Value::Array(v) => Ok(Value::from(v.len() as f64)),
+        Value::Object(v) => Ok(Value::from(v.len() as f64)),
+        v => Err(invalid_argument(name, v)),
+    }
+}
+
+fn lower(name: &str, args: &[Value]) -> Result<Value, EvaluatorError> {
+    Ok(Value::String(string(name, &args[0])?.to_lowercase()))
+}
+
+fn upper(name: &str, args: &[Value]) -> Result<Value, EvaluatorError> {
+    Ok(Value::String(string(name, &args[0])?.to_uppercase()))
+}
+
+fn trim(name: &str, args: &[Value]) -> Result<Value, EvaluatorError> {
+    Ok(Value::from(string(name, &args[0])?.trim()))
+}
+
+fn abs(name: &str, args: &[Value]) -> Result<Value, EvaluatorError> {
+    Ok(Value::Number(number(name, &args[0])?.abs()))
+}
+
+fn floor(name: &str, args: &[Value]) -> Result<Value, EvaluatorError> {
+    Ok(Value::Number(number(name, &args[0])?.floor()))
+}
+
+fn ceil(name: &str, args: &[Value]) -> Result<Value, EvaluatorError> {
+    Ok(Value::Number(number(name, &args[0])?.ceil()))
+}
+
+fn round(name: &str, args: &[Value]) -> Result<Value, EvaluatorError> {
+    let v = number(name, &args[0])?;
+    let digits = match args.get(1) {
+        None => return Ok(Value::Number(v.round())),
+        Some(digits) => number(name, digits)?.trunc(),
+    };
+
+    // scaling past the range of f64 keeps every digit of `v`, or rounds all of them away
+    let factor = 10_f64.powf(digits.abs());
+    let rounded = if digits >= 0.0 {
+        match v * factor {
+            scaled if scaled.is_finite() => scaled.round() / factor,
+            _ => v,
+        }
+    } else if factor.is_finite() {
+        (v / factor).round() * factor
+    } else {
+        0.0
+    };
+    Ok(Value::Number(if rounded.is_finite() { rounded } else { v }))
+}
+
+/// Numbers to compare for `min`/`max`: either the arguments themselves or
+/// the elements of a single array argument.
+fn numbers(name: &str, args: &[Value]) -> Result<Vec<f64>, EvaluatorError> {
+    let values = match args {
+        [Value::Array(v)] if !v.is_empty() => v.as_slice(),
+        [v @ Value::Array(_)] => return Err(invalid_argument(name, v)),
+        _ => args,
+    };
+
+    values.iter().map(|v| number(name, v)).collect()
+}
+
+fn min(name: &str, args: &[Value]) -> Result<Value, EvaluatorError> {
+    let values = numbers(name, args)?;
+    Ok(Value::Number(
+        values.into_iter().fold(f64::INFINITY, f64::min),
+    ))
+}
+
+fn max(name: &str, args: &[Value]) -> Result<Value, EvaluatorError> {
+    let values = numbers(name, args)?;
+    Ok(Value::Number(
+        values.into_iter().fold(f64::NEG_INFINITY, f64::max),
+    ))
+}
+
+fn contains(name: &str, args: &[Value]) -> Result<Value, EvaluatorError> {
+    match (&args[0], &args[1]) {
+        (Value::String(l), Value::String(r)) => Ok(Value::Bool(l.contains(r.as_str()))),
+        (Value::String(_), v) => Err(invalid_argument(name, v)),
+        (Value::Array(l), r) => Ok(Value::Bool(l.contains(r))),
+        (Value::Object(l), Value::String(r)) => Ok(Value::Bool(l.contains_key(r))),
+        (Value::Object(_), v) => Err(invalid_argument(name, v)),
+        (v, _) => Err(invalid_argument(name, v)),
+    }
+}
+
+fn starts_with(name: &str, args: &[Value]) -> Result<Value, EvaluatorError> {
+    let (l, r) = (string(name, &args[0])?, string(name, &args[1])?);
+    Ok(Value::Bool(l.starts_with(r)))
+}
+
+fn ends_with(name: &str, args: &[Value]) -> Result<Value, EvaluatorError> {
+    let (l, r) = (string(name, &args[0])?, string(name, &args[1])?);
+    Ok(Value::Bool(l.ends_with(r)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_arity() {
+        assert!(Arity::Exact(1).accepts(1));
+        assert!(!Arity::Exact(1).accepts(2));
+        assert!(Arity::Range(1, 2).accepts(2));
+        assert!(!Arity::Range(1, 2).accepts(0));
+        assert!(Arity::AtLeast(1).accepts(5));
+        assert!(!Arity::AtLeast(1).accepts(0));
+
+        assert_eq!(format!("{}", Arity::Exact(1)), "1");
+        assert_eq!(format!("{}", Arity::Range(1, 2)), "1 to 2");
+        assert_eq!(format!("{}", Arity::AtLeast(1)), "at least 1");
+    }
+
+    #[test]
+    fn test_signature() {
+        let signature = Signature::new(vec![ValueType::String, ValueType::Any]);
+        assert_eq!(signature.arity(), Arity::Exact(2));
+        assert_eq!(signature.param(0), Some(ValueType::String));
+        assert_eq!(signature.param(2), None);
+
+        let signature = Signature::variadic(vec![], ValueType::Number);
+        assert_eq!(signature.arity(), Arity::AtLeast(0));
+        assert_eq!(signature.param(3), Some(ValueType::Number));
+    }
+
+    #[test]
+    fn test_builtin_lookup() {
+        assert_eq!(builtin("len").map(|b| b.arity), Some(Arity::Exact(1)));
+        assert_eq!(builtin("round").map(|b| b.arity), Some(Arity::Range(1, 2)));
+        assert!(builtin("unknown").is_none());
+
+        let contains = builtin("contains").unwrap();
+        assert_eq!(contains.param(0), COLLECTION);
+        assert_eq!(contains.param(1), ANY);
+        assert_eq!(builtin("max").unwrap().param(3), NUMBERS);
+    }
+}
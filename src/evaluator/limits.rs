@@ -0,0 +1,190 @@
+use std::time::{Duration, Instant};
+
+use crate::ast::value::Value;
+use crate::evaluator::evaluator::EvaluatorError;
+
+/// Resource limits for parsing and evaluating untrusted expressions; every limit is off by
+/// default.
+///
+/// Length counts the bytes of the source of an expression. Depth counts nested expressions and
+/// values, the root being at depth 1. Nodes count expressions and array or object elements.
+/// Steps count the expressions evaluated.
+///
+/// The length is checked before parsing, depth and nodes while parsing: the parse stops at the
+/// first node crossing a limit.
+///
+/// # Examples
+///
+/// ```
+/// use evaluator_rs::*;
+/// use std::collections::HashMap;
+/// use std::time::Duration;
+///
+/// let limits = Limits::new()
+///     .with_max_length(256)
+///     .with_max_depth(16)
+///     .with_max_steps(1000);
+///
+/// let expr = parse_expr_from_str_with_limits("((((1))))", &limits).unwrap();
+/// let rs = Evaluator::new()
+///     .with_limits(limits.with_timeout(Duration::from_millis(10)))
+///     .evaluate(&expr, &HashMap::<&str, Value>::new())
+///     .unwrap();
+/// assert_eq!(rs, Value::from(1));
+/// ```
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Limits {
+    pub(crate) max_length: Option<usize>,
+    pub(crate) max_depth: Option<usize>,
+    pub(crate) max_nodes: Option<usize>,
+    pub(crate) max_steps: Option<usize>,
+    pub(crate) timeout: Option<Duration>,
+}
+
+impl Limits {
+    pub fn new() -> Self {
+        Limits::default()
+    }
+
+    /// Maximum length in bytes of the source of a parsed expression
+    pub fn with_max_length(mut self, length: usize) -> Self {
+        self.max_length = Some(length);
+        self
+    }
+
+    /// Maximum nesting depth of a parsed or evaluated expression
+    pub fn with_max_depth(mut self, depth: usize) -> Self {
+        self.max_depth = Some(depth);
+        self
+    }
+
+    /// Maximum number of nodes of a parsed expression
+    pub fn with_max_nodes(mut self, nodes: usize) -> Self {
+        self.max_nodes = Some(nodes);
+        self
+    }
+
+    /// Maximum number of steps of one evaluation
+    pub fn with_max_steps(mut self, steps: usize) -> Self {
+        self.max_steps = Some(steps);
+        self
+    }
+
+    /// Maximum wall-clock time of one evaluation
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+}
+
+/// Limit of `Limits` exceeded by an expression
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum Exceeded {
+    Length(usize),
+    Depth(usize),
+    Nodes(usize),
+}
+
+/// Check the length of the `source` of an expression, before parsing it
+pub(crate) fn enforce_length(source: &str, limits: &Limits) -> Result<(), Exceeded> {
+    match limits.max_length {
+        Some(max) if source.len() > max => Err(Exceeded::Length(max)),
+        _ => Ok(()),
+    }
+}
+
+/// Depth and node count of an expression being parsed, checked against `Limits` as its nodes
+/// are built
+#[derive(Debug, Default)]
+pub(crate) struct Size {
+    max_depth: Option<usize>,
+    max_nodes: Option<usize>,
+    nodes: usize,
+}
+
+impl Size {
+    pub(crate) fn new(limits: &Limits) -> Self {
+        Size {
+            max_depth: limits.max_depth,
+            max_nodes: limits.max_nodes,
+            nodes: 0,
+        }
+    }
+
+    /// Count an expression node at `depth`: its nesting when parsing top-down, or the levels
+    /// of nodes up to and including it when parsing bottom-up
+    pub(crate) fn node(&mut self, depth: usize) -> Result<(), Exceeded> {
+        if let Some(max) = self.max_depth.filter(|max| depth > *max) {
+            return Err(Exceeded::Depth(max));
+        }
+        self.count()
+    }
+
+    /// Count the elements of `value`, returning how many levels they nest below it
+    pub(crate) fn elements(&mut self, value: &Value) -> Result<usize, Exceeded> {
+        let mut levels = 0;
+        let mut stack = vec![(value, 0)];
+        while let Some((value, depth)) = stack.pop() {
+            if depth > 0 {
+                self.count()?;
+            }
+            levels = levels.max(depth);
+            match value {
+                Value::Array(items) => stack.extend(items.iter().map(|v| (v, depth + 1))),
+                Value::Object(fields) => stack.extend(fields.values().map(|v| (v, depth + 1))),
+                _ => {}
+            }
+        }
+
+        Ok(levels)
+    }
+
+    fn count(&mut self) -> Result<(), Exceeded> {
+        self.nodes += 1;
+        match self.max_nodes {
+            Some(max) if self.nodes > max => Err(Exceeded::Nodes(max)),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Steps and deadline of one evaluation
+pub(crate) struct Budget {
+    limits: Limits,
+    steps: usize,
+    deadline: Option<Instant>,
+}
+
+/// Steps between two checks of the deadline
+const DEADLINE_INTERVAL: usize = 64;
+
+impl Budget {
+    pub fn new(limits: &Limits) -> Self {
+        Budget {
+            limits: *limits,
+            steps: 0,
+            // a timeout too large to represent as an instant never expires
+            deadline: limits
+                .timeout
+                .and_then(|timeout| Instant::now().checked_add(timeout)),
+        }
+    }
+
+    /// Account for evaluating one more expression, nested at `depth`
+    pub fn step(&mut self, depth: usize) -> Result<(), EvaluatorError> {
+        self.steps += 1;
+        if let Some(max) = self.limits.max_depth.filter(|max| depth > *max) {
+            return Err(EvaluatorError::DepthLimitExceeded(max));
+        }
+        if let Some(max) = self.limits.max_steps.filter(|max| self.steps > *max) {
+            return Err(EvaluatorError::StepLimitExceeded(max));
+        }
+        if let (Some(deadline), Some(timeout)) = (self.deadline, self.limits.timeout) {
+            if (self.steps - 1) % DEADLINE_INTERVAL == 0 && Instant::now() >= deadline {
+                return Err(EvaluatorError::Timeout(timeout));
+            }
+        }
+
+        Ok(())
+    }
+}
@@ -1 +1,7 @@
+pub mod compiler;
+pub mod context;
 pub mod evaluator;
+pub mod explain;
+pub mod function;
+pub mod limits;
+pub mod regex_cache;
@@ -0,0 +1,175 @@
+use regex::Regex;
+use std::collections::HashMap;
+use std::fmt::{self, Debug, Formatter};
+use std::sync::Mutex;
+
+use crate::evaluator::evaluator::EvaluatorError;
+
+/// Default number of patterns kept by a `RegexCache`
+pub const DEFAULT_REGEX_CACHE_CAPACITY: usize = 256;
+
+/// Bounded cache of the regexes compiled for the `matches` operator, evicting the least recently
+/// used pattern once full.
+///
+/// Each evaluator owns a cache; share one between evaluators with `Evaluator::with_regex_cache`.
+/// Constant patterns of compiled expressions are compiled once and never go through the cache.
+///
+/// # Examples
+///
+/// ```
+/// use evaluator_rs::*;
+/// use std::collections::HashMap;
+/// use std::sync::Arc;
+///
+/// let cache = Arc::new(RegexCache::new(16));
+/// let evaluator = Evaluator::new().with_regex_cache(cache.clone());
+///
+/// let expr = parse_expr_from_str("{sku} matches {pattern}").unwrap();
+/// let parameters = HashMap::from([
+///     ("sku", Value::from("AB-1234")),
+///     ("pattern", Value::from(r"^[A-Z]{2}-\d{4}$")),
+/// ]);
+/// assert_eq!(evaluator.evaluate(&expr, &parameters).unwrap(), Value::from(true));
+/// assert!(cache.contains(r"^[A-Z]{2}-\d{4}$"));
+/// ```
+pub struct RegexCache {
+    capacity: usize,
+    entries: Mutex<Entries>,
+}
+
+#[derive(Default)]
+struct Entries {
+    /// Compiled regex of each pattern along with the tick of its last use
+    regexes: HashMap<String, (Regex, u64)>,
+    tick: u64,
+}
+
+impl Default for RegexCache {
+    fn default() -> Self {
+        RegexCache::new(DEFAULT_REGEX_CACHE_CAPACITY)
+    }
+}
+
+impl Debug for RegexCache {
+    fn fmt(&self, fmt: &mut Formatter) -> fmt::Result {
+        fmt.debug_struct("RegexCache")
+            .field("capacity", &self.capacity)
+            .field("len", &self.len())
+            .finish()
+    }
+}
+
+impl RegexCache {
+    /// Cache keeping at most `capacity` patterns; a capacity of 0 disables caching
+    pub fn new(capacity: usize) -> Self {
+        RegexCache {
+            capacity,
+            entries: Mutex::default(),
+        }
+    }
+
+    /// Number of cached patterns
+    pub fn len(&self) -> usize {
+        self.lock().regexes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Whether the regex of `pattern` is cached
+    pub fn contains(&self, pattern: &str) -> bool {
+        self.lock().regexes.contains_key(pattern)
+    }
+
+    /// Regex of `pattern`, compiled on a miss; invalid patterns are not cached
+    pub(crate) fn get(&self, pattern: &str) -> Result<Regex, EvaluatorError> {
+        if let Some(regex) = self.lock().hit(pattern) {
+            return Ok(regex);
+        }
+
+        let regex = compile_regex(pattern)?;
+        if self.capacity > 0 {
+            self.lock().insert(pattern, &regex, self.capacity);
+        }
+
+        Ok(regex)
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Entries> {
+        // entries are consistent between statements, so a panic elsewhere cannot poison them
+        self.entries.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl Entries {
+    fn hit(&mut self, pattern: &str) -> Option<Regex> {
+        self.tick += 1;
+        let (regex, used) = self.regexes.get_mut(pattern)?;
+        *used = self.tick;
+        Some(regex.clone())
+    }
+
+    /// Cache the regex of `pattern`, evicting the least recently used pattern if full. The lock
+    /// is released while compiling, so another thread may have cached the pattern meanwhile, in
+    /// which case it is only replaced.
+    fn insert(&mut self, pattern: &str, regex: &Regex, capacity: usize) {
+        if !self.regexes.contains_key(pattern) && self.regexes.len() >= capacity {
+            self.evict();
+        }
+        self.tick += 1;
+        self.regexes
+            .insert(pattern.to_owned(), (regex.clone(), self.tick));
+    }
+
+    /// Remove the least recently used pattern
+    fn evict(&mut self) {
+        let oldest = self
+            .regexes
+            .iter()
+            .min_by_key(|(_, (_, used))| *used)
+            .map(|(pattern, _)| pattern.clone());
+        if let Some(pattern) = oldest {
+            self.regexes.remove(&pattern);
+        }
+    }
+}
+
+/// Compile `pattern` without caching
+pub(crate) fn compile_regex(pattern: &str) -> Result<Regex, EvaluatorError> {
+    Regex::new(pattern).map_err(|e| EvaluatorError::InvalidRegex(pattern.to_owned(), e.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_regex_cache() {
+        let cache = RegexCache::new(2);
+        assert!(cache.get("a").unwrap().is_match("a"));
+        assert!(cache.get("b").unwrap().is_match("b"));
+        assert!(cache.get("a").is_ok());
+        // `b` is the least recently used pattern
+        assert!(cache.get("c").is_ok());
+        assert_eq!(cache.len(), 2);
+        assert!(cache.contains("a") && cache.contains("c"));
+        assert!(!cache.contains("b"));
+
+        match cache.get("(") {
+            Err(EvaluatorError::InvalidRegex(pattern, _)) => assert_eq!(pattern, "("),
+            output => panic!("unexpected output {:?}", output),
+        }
+        assert!(!cache.contains("("));
+
+        // a pattern cached by another thread while this one compiled it evicts nothing
+        let regex = compile_regex("a").unwrap();
+        cache.lock().insert("a", &regex, 2);
+        assert_eq!(cache.len(), 2);
+        assert!(cache.contains("a") && cache.contains("c"));
+
+        let cache = RegexCache::new(0);
+        assert!(cache.get("a").is_ok());
+        assert!(cache.is_empty());
+    }
+}
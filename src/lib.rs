@@ -48,13 +48,44 @@
 //! ```
 
 #![allow(unused)]
+#![allow(clippy::module_inception)]
 #[macro_use]
 extern crate lalrpop_util;
 
 mod ast;
+mod checker;
 mod evaluator;
+mod optimizer;
 mod parser;
+mod rules;
+#[cfg(test)]
+mod test_util;
 
-pub use ast::{expr::Expr, value::Value};
-pub use evaluator::evaluator::{evaluate, EvaluatorError};
-pub use parser::parser::{parse_expr_from_json, parse_expr_from_str, ParserError};
+pub use ast::{
+    expr::{Expr, NodePath, NodeStep, PathSegment},
+    value::{Value, ValueType},
+};
+pub use checker::checker::{type_check, Type, TypeError};
+pub use evaluator::compiler::{compile, CompiledExpr};
+pub use evaluator::context::Context;
+pub use evaluator::evaluator::{
+    evaluate, evaluate_array, evaluate_as, evaluate_bool, evaluate_number, evaluate_serializable,
+    evaluate_string, validate_parameters, Evaluator, EvaluatorError, Located, LocatedError,
+};
+pub use evaluator::explain::{explain, Explanation, Outcome, Trace};
+pub use evaluator::function::{Arity, FunctionError, Signature};
+pub use evaluator::limits::Limits;
+pub use evaluator::regex_cache::RegexCache;
+pub use optimizer::optimizer::{optimize, partial_evaluate};
+pub use parser::parser::{
+    expr_to_json, parse_expr_from_json, parse_expr_from_json_with_limits, parse_expr_from_str,
+    parse_expr_from_str_with_limits, parse_expr_with_source_map, ParserError, SourceMap,
+    SyntaxError,
+};
+pub use rules::loader::{
+    load_rule_bundle, load_rule_bundle_with_limits, parse_rule_bundle,
+    parse_rule_bundle_with_limits, BundleError, Format, Metadata, RuleBundle,
+};
+pub use rules::rules::{
+    evaluate_rules, FiredRule, Rule, RuleError, RuleSet, RuleSetResult, Strategy,
+};
@@ -0,0 +1,547 @@
+use crate::ast::{
+    expr::{Expr, NodePath},
+    op::{LogicalOp, Op, UnaryOp},
+    value::Value,
+};
+use crate::evaluator::context::Context;
+use crate::evaluator::evaluator::{
+    evaluate_binary_expr, evaluate_unary_expr, resolve_path, short_circuit, with_default_evaluator,
+    EvaluatorError, LocatedError,
+};
+use crate::evaluator::regex_cache::RegexCache;
+use std::collections::BTreeMap;
+
+/// Fold constant subtrees and simplify boolean identities.
+///
+/// The result evaluates identically to `expr`: operations that would fail are kept so the error
+/// is still reported at evaluation time, and function calls are never folded since host functions
+/// may replace the built-in ones.
+///
+/// # Examples
+///
+/// ```
+/// use evaluator_rs::*;
+///
+/// let expr = parse_expr_from_str("(1 + 2) * {x} > 6 && true").unwrap();
+/// assert_eq!(optimize(&expr).to_string(), "((3 * {x}) > 6)");
+/// ```
+pub fn optimize(expr: &Expr) -> Expr {
+    partial_evaluate(expr, &BTreeMap::<&str, Value>::new())
+        .expect("no path to resolve without known values")
+}
+
+/// Substitute the identifiers defined in `known`, then fold and simplify as `optimize` does.
+///
+/// The residual expression refers only to the identifiers missing from `known` and evaluates
+/// identically to `expr` once they are provided. A path into a known value that cannot be
+/// resolved fails with the `EvaluatorError::InvalidPath` evaluation would report, located at the
+/// path, unless folding drops it: it fails even where evaluation could skip it at run time.
+///
+/// # Examples
+///
+/// ```
+/// use evaluator_rs::*;
+/// use std::collections::HashMap;
+///
+/// let expr = parse_expr_from_str("{tier} == 'gold' && {amount} > {limit} * 2").unwrap();
+/// let known = HashMap::from([("tier", Value::from("gold")), ("limit", Value::from(50))]);
+/// let residual = partial_evaluate(&expr, &known).unwrap();
+/// assert_eq!(residual.to_string(), "({amount} > 100)");
+///
+/// let known = HashMap::from([("tier", Value::from("silver"))]);
+/// assert_eq!(partial_evaluate(&expr, &known), Ok(Expr::Value(Value::from(false))));
+///
+/// let expr = parse_expr_from_str("{amount} > {tenant.limit}").unwrap();
+/// let known = HashMap::from([("tenant", Value::from(50))]);
+/// let error = partial_evaluate(&expr, &known).unwrap_err();
+/// assert_eq!(error.to_string(), "invalid path tenant.limit: cannot resolve .limit at /rhs");
+/// ```
+pub fn partial_evaluate<C: Context + ?Sized>(expr: &Expr, known: &C) -> Result<Expr, LocatedError> {
+    with_default_evaluator(|evaluator| simplify(expr, known, evaluator.regexes()))
+}
+
+fn simplify<C: Context + ?Sized>(
+    root: &Expr,
+    known: &C,
+    regexes: &RegexCache,
+) -> Result<Expr, LocatedError> {
+    let mut tasks = vec![Task::Simplify(root)];
+    let mut exprs = vec![];
+
+    while let Some(task) = tasks.pop() {
+        let expr = match task {
+            Task::Simplify(expr) => match expr {
+                Expr::Value(v) => Expr::Value(v.clone()),
+                Expr::Identifier(name) => match known.get(name) {
+                    Some(v) => Expr::Value(v.into_owned()),
+                    None => Expr::Identifier(name.clone()),
+                },
+                Expr::Path(name, segments) => match known.get(name) {
+                    Some(value) => match resolve_path(name, &value, segments) {
+                        Ok(v) => Expr::Value(v.clone()),
+                        Err(error) => {
+                            let path = path_of(root, expr);
+                            return Err(LocatedError { error, path });
+                        }
+                    },
+                    None => Expr::Path(name.clone(), segments.clone()),
+                },
+                Expr::Op(lhs, Op::Logical(op), rhs) => {
+                    tasks.extend([Task::Logical(*op, rhs), Task::Simplify(lhs)]);
+                    continue;
+                }
+                Expr::Op(lhs, op, rhs) => {
+                    tasks.extend([Task::Op(*op), Task::Simplify(rhs), Task::Simplify(lhs)]);
+                    continue;
+                }
+                Expr::Unary(op, rhs) => {
+                    tasks.extend([Task::Unary(*op), Task::Simplify(rhs)]);
+                    continue;
+                }
+                Expr::Call(name, args) => {
+                    tasks.push(Task::Call(name, args.len()));
+                    tasks.extend(args.iter().rev().map(Task::Simplify));
+                    continue;
+                }
+                Expr::Conditional(cond, then, otherwise) => {
+                    tasks.extend([Task::Branch(then, otherwise), Task::Simplify(cond)]);
+                    continue;
+                }
+            },
+            Task::Logical(op, rhs) => {
+                match exprs.last() {
+                    Some(Expr::Value(l)) if short_circuit(l, &op).is_some() => {}
+                    _ => tasks.extend([Task::Op(Op::Logical(op)), Task::Simplify(rhs)]),
+                }
+                continue;
+            }
+            Task::Op(op) => {
+                let rhs = exprs.pop().expect("operand simplified");
+                let lhs = exprs.pop().expect("operand simplified");
+                optimize_op(lhs, op, rhs, regexes)
+            }
+            Task::Unary(op) => {
+                let rhs = exprs.pop().expect("operand simplified");
+                match &rhs {
+                    Expr::Value(v) => {
+                        let result = evaluate_unary_expr(&op, v);
+                        fold(Expr::Unary(op, Box::new(rhs)), result)
+                    }
+                    _ => Expr::Unary(op, Box::new(rhs)),
+                }
+            }
+            Task::Call(name, count) => {
+                Expr::Call(name.to_owned(), exprs.split_off(exprs.len() - count))
+            }
+            Task::Branch(then, otherwise) => {
+                match exprs.last() {
+                    Some(Expr::Value(Value::Bool(true))) => {
+                        exprs.pop();
+                        tasks.push(Task::Simplify(then));
+                    }
+                    Some(Expr::Value(Value::Bool(false))) => {
+                        exprs.pop();
+                        tasks.push(Task::Simplify(otherwise));
+                    }
+                    _ => tasks.extend([
+                        Task::Conditional,
+                        Task::Simplify(otherwise),
+                        Task::Simplify(then),
+                    ]),
+                }
+                continue;
+            }
+            Task::Conditional => {
+                let otherwise = exprs.pop().expect("branch simplified");
+                let then = exprs.pop().expect("branch simplified");
+                let cond = exprs.pop().expect("condition simplified");
+                Expr::Conditional(Box::new(cond), Box::new(then), Box::new(otherwise))
+            }
+        };
+        exprs.push(expr);
+    }
+
+    Ok(exprs.pop().expect("expression simplified"))
+}
+
+/// Path of `node`, a subexpression of `root`
+fn path_of(root: &Expr, node: &Expr) -> NodePath {
+    let mut found = NodePath::default();
+    root.visit(|expr, path| {
+        if std::ptr::eq(expr, node) {
+            found = NodePath(path.to_vec());
+        }
+    });
+    found
+}
+
+/// Unit of work of the non-recursive partial evaluator
+enum Task<'e> {
+    Simplify(&'e Expr),
+    /// Simplify the right operand unless the topmost expression decides the operation
+    Logical(LogicalOp, &'e Expr),
+    /// Combine the two topmost operands
+    Op(Op),
+    Unary(UnaryOp),
+    /// Call a function with the topmost expressions as arguments
+    Call(&'e str, usize),
+    /// Keep one branch if the topmost condition is constant, or simplify both
+    Branch(&'e Expr, &'e Expr),
+    /// Build a conditional from the three topmost expressions
+    Conditional,
+}
+
+fn optimize_op(lhs: Expr, op: Op, rhs: Expr, regexes: &RegexCache) -> Expr {
+    if let (Expr::Value(l), Op::Logical(o)) = (&lhs, op) {
+        if let Some(v) = short_circuit(l, &o) {
+            return Expr::Value(v);
+        }
+    }
+
+    if let (Expr::Value(l), Expr::Value(r)) = (&lhs, &rhs) {
+        let result = evaluate_binary_expr(l, &op, r, regexes);
+        return fold(Expr::Op(Box::new(lhs), op, Box::new(rhs)), result);
+    }
+
+    // `true && x` and `false || x` are `x` as long as `x` evaluates to a boolean
+    if let Op::Logical(o) = op {
+        let identity = Value::Bool(o == LogicalOp::And);
+        if matches!(&lhs, Expr::Value(l) if *l == identity) && is_boolean(&rhs) {
+            return rhs;
+        }
+        if matches!(&rhs, Expr::Value(r) if *r == identity) && is_boolean(&lhs) {
+            return lhs;
+        }
+    }
+
+    Expr::Op(Box::new(lhs), op, Box::new(rhs))
+}
+
+/// Value of a constant operation, or the operation itself if it fails. Non-finite numbers are
+/// not folded since they have no literal form.
+fn fold(expr: Expr, result: Result<Value, EvaluatorError>) -> Expr {
+    match result {
+        Ok(Value::Number(v)) if !v.is_finite() => expr,
+        Ok(v) => Expr::Value(v),
+        Err(_) => expr,
+    }
+}
+
+/// Whether `expr` evaluates to a boolean whenever it evaluates successfully
+fn is_boolean(expr: &Expr) -> bool {
+    let mut stack = vec![expr];
+    while let Some(expr) = stack.pop() {
+        let boolean = match expr {
+            Expr::Value(v) => matches!(v, Value::Bool(_)),
+            Expr::Op(_, op, _) => matches!(op, Op::Logical(_) | Op::Relational(_)),
+            Expr::Unary(op, _) => *op == UnaryOp::Not,
+            Expr::Conditional(_, then, otherwise) => {
+                stack.extend([then.as_ref(), otherwise.as_ref()]);
+                true
+            }
+            Expr::Identifier(_) | Expr::Path(_, _) | Expr::Call(_, _) => false,
+        };
+        if !boolean {
+            return false;
+        }
+    }
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::expr::{NodeStep, PathSegment};
+    use crate::evaluator::evaluator::evaluate;
+    use crate::parser::parser;
+    use crate::test_util::{chain, deep_exprs, deep_parameters, PASS_DEPTH};
+    use std::collections::HashMap;
+
+    struct TestCase {
+        expr: &'static str,
+        want: &'static str,
+    }
+
+    #[test]
+    fn test_optimize() {
+        let test_cases: Vec<TestCase> = vec![
+            TestCase {
+                expr: "(1 + 2) * {x}",
+                want: "(3 * {x})",
+            },
+            TestCase {
+                expr: "true && {flag}",
+                want: "(true && {flag})",
+            },
+            TestCase {
+                expr: "true && {x} > 1",
+                want: "({x} > 1)",
+            },
+            TestCase {
+                expr: "false || !{flag}",
+                want: "!{flag}",
+            },
+            TestCase {
+                expr: "{x} == 1 || false",
+                want: "({x} == 1)",
+            },
+            TestCase {
+                expr: "false && {missing}",
+                want: "false",
+            },
+            TestCase {
+                expr: "{x} > 1 || 1 < 2",
+                want: "(({x} > 1) || true)",
+            },
+            TestCase {
+                expr: "1 + true",
+                want: "(1 + true)",
+            },
+            TestCase {
+                expr: "-'a' == 1",
+                want: "(-'a' == 1)",
+            },
+            TestCase {
+                expr: "1 / 0",
+                want: "(1 / 0)",
+            },
+            TestCase {
+                expr: "'ab-1' matches '^[a-z]+-\\\\d$'",
+                want: "true",
+            },
+            TestCase {
+                expr: "if 2 > 1 then {x} else {missing}",
+                want: "{x}",
+            },
+            TestCase {
+                expr: "if 1 then {x} else 2",
+                want: "(if 1 then {x} else 2)",
+            },
+            TestCase {
+                expr: "len('ab') + (2 * 3)",
+                want: "(len('ab') + 6)",
+            },
+            TestCase {
+                expr: "{x} + 1 + 2",
+                want: "(({x} + 1) + 2)",
+            },
+        ];
+        test_cases.iter().for_each(|case| {
+            let expr = parser::parse_expr_from_str(case.expr).unwrap();
+            assert_eq!(
+                optimize(&expr).to_string(),
+                case.want,
+                "expr: {}",
+                case.expr
+            );
+        });
+
+        let cached =
+            with_default_evaluator(|evaluator| evaluator.regexes().contains("^[a-z]+-\\d$"));
+        assert!(cached);
+    }
+
+    #[test]
+    fn test_optimize_evaluates_identically() {
+        let exprs = vec![
+            "(1 + 2) * {x} > 6 && true",
+            "true && {flag}",
+            "false || {flag}",
+            "{flag} && true",
+            "true && {x}",
+            "1 + true",
+            "{x} + 'a' == 'a'",
+            "if {x} > 1 then 1 + 1 else {x} * (2 - 3)",
+            "if 1 then {x} else 2",
+            "!(1 > 2) && {x} in [1, 2, 3]",
+            "-(2 * 3) + {x}",
+            "max(1 + 1, {x})",
+            "0 - 5",
+            "{x} * (1 - 3.5)",
+        ];
+        let parameters_list = vec![
+            HashMap::from([("x", Value::from(2)), ("flag", Value::from(true))]),
+            HashMap::from([("x", Value::from(5)), ("flag", Value::from(1))]),
+            HashMap::from([("x", Value::from("a"))]),
+        ];
+        for s in exprs {
+            let expr = parser::parse_expr_from_str(s).unwrap();
+            let optimized = optimize(&expr);
+            assert_eq!(
+                parser::parse_expr_from_str(&optimized.to_string()).as_deref(),
+                Ok(&optimized),
+                "expr: {}",
+                s
+            );
+            for parameters in &parameters_list {
+                assert_eq!(
+                    evaluate(&expr, parameters).map_err(|e| e.error),
+                    evaluate(&optimized, parameters).map_err(|e| e.error),
+                    "expr: {}, optimized: {}",
+                    s,
+                    optimized
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_partial_evaluate() {
+        let known = HashMap::from([
+            ("tier", Value::from("gold")),
+            ("limit", Value::from(50)),
+            ("enabled", Value::from(false)),
+            (
+                "tenant",
+                Value::from(&serde_json::json!({ "region": "eu" })),
+            ),
+        ]);
+        let test_cases: Vec<TestCase> = vec![
+            TestCase {
+                expr: "{tier} == 'gold' && {amount} > {limit} * 2",
+                want: "({amount} > 100)",
+            },
+            TestCase {
+                expr: "{enabled} && {amount} > 1",
+                want: "false",
+            },
+            TestCase {
+                expr: "{amount} > 1 && {enabled}",
+                want: "(({amount} > 1) && false)",
+            },
+            TestCase {
+                expr: "{tenant.region} == {region}",
+                want: "('eu' == {region})",
+            },
+            TestCase {
+                expr: "{enabled} && {tenant.zone} == {region}",
+                want: "false",
+            },
+            TestCase {
+                expr: "if {tier} == 'gold' then {amount} * 0.9 else {amount}",
+                want: "({amount} * 0.9)",
+            },
+            TestCase {
+                expr: "lower({tier}) == {name}",
+                want: "(lower('gold') == {name})",
+            },
+            TestCase {
+                expr: "{limit} + {tier}",
+                want: "(50 + 'gold')",
+            },
+        ];
+        test_cases.iter().for_each(|case| {
+            let expr = parser::parse_expr_from_str(case.expr).unwrap();
+            let residual = partial_evaluate(&expr, &known).unwrap();
+            assert_eq!(residual.to_string(), case.want, "expr: {}", case.expr);
+        });
+
+        let expr = parser::parse_expr_from_str("{req} > 1 && {tenant.zone} == 'eu'").unwrap();
+        assert_eq!(
+            partial_evaluate(&expr, &known),
+            Err(LocatedError {
+                error: EvaluatorError::InvalidPath(
+                    "tenant.zone".to_owned(),
+                    PathSegment::Field("zone".to_owned())
+                ),
+                path: NodePath(vec![NodeStep::Rhs, NodeStep::Lhs]),
+            })
+        );
+    }
+
+    #[test]
+    fn test_partial_evaluate_evaluates_identically() {
+        let exprs = vec![
+            "{tier} == 'gold' && {amount} > {limit} * 2",
+            "{amount} > 1 || {enabled}",
+            "if {enabled} then {missing} else {amount} - {limit}",
+            "{tenant.region} == {region} || {region} in ['us']",
+            "{limit} + {tier}",
+            "max({limit}, {amount}) == {amount}",
+            "{tenant.zone} == 'eu' && {amount} > 1",
+            "{amount} > 100 && {tenant.region[0]} == 'e'",
+        ];
+        let known = HashMap::from([
+            ("tier", Value::from("gold")),
+            ("limit", Value::from(50)),
+            ("enabled", Value::from(false)),
+            (
+                "tenant",
+                Value::from(&serde_json::json!({ "region": "eu" })),
+            ),
+        ]);
+        let requests = [
+            HashMap::from([("amount", Value::from(120)), ("region", Value::from("eu"))]),
+            HashMap::from([("amount", Value::from(1)), ("region", Value::from("us"))]),
+            HashMap::from([("amount", Value::from("x"))]),
+        ];
+        for s in exprs {
+            let expr = parser::parse_expr_from_str(s).unwrap();
+            let results: Vec<_> = requests
+                .iter()
+                .map(|request| {
+                    let mut parameters = known.clone();
+                    parameters.extend(request.iter().map(|(k, v)| (*k, v.clone())));
+                    evaluate(&expr, &parameters).map_err(|e| e.error)
+                })
+                .collect();
+
+            let residual = match partial_evaluate(&expr, &known) {
+                Ok(residual) => residual,
+                // an unresolvable path fails as evaluation does when it reaches the path
+                Err(e) => {
+                    assert!(results.contains(&Err(e.error)), "expr: {}", s);
+                    continue;
+                }
+            };
+            assert!(
+                residual
+                    .identifiers()
+                    .iter()
+                    .all(|name| !known.contains_key(name)),
+                "residual: {}",
+                residual
+            );
+            for (request, result) in requests.iter().zip(results) {
+                assert_eq!(
+                    evaluate(&residual, request).map_err(|e| e.error),
+                    result,
+                    "expr: {}, residual: {}",
+                    s,
+                    residual
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_partial_evaluate_deep_expr() {
+        let parameters = deep_parameters();
+        for (s, _) in deep_exprs(PASS_DEPTH) {
+            let expr = parser::parse_expr_from_str(&s).unwrap();
+            assert_eq!(
+                evaluate(&optimize(&expr), &parameters).map_err(|e| e.error),
+                evaluate(&expr, &parameters).map_err(|e| e.error)
+            );
+        }
+
+        let known = HashMap::from([("a", Value::from(false))]);
+        let expr = parser::parse_expr_from_str(&chain("{a}", PASS_DEPTH)).unwrap();
+        assert_eq!(
+            partial_evaluate(&expr, &known),
+            Ok(Expr::Value(Value::from(false)))
+        );
+
+        let branches = "if {a} then true else ".repeat(PASS_DEPTH);
+        let expr = parser::parse_expr_from_str(&format!("true && ({}false)", branches)).unwrap();
+        assert_eq!(
+            evaluate(&optimize(&expr), &parameters),
+            Ok(Value::from(false))
+        );
+        let expr = parser::parse_expr_from_str(&(branches + "{n}")).unwrap();
+        assert_eq!(
+            partial_evaluate(&expr, &known),
+            Ok(Expr::Identifier("n".to_owned()))
+        );
+    }
+}
@@ -1,12 +1,18 @@
+use lalrpop_util::ParseError;
 use serde::{Deserialize, Serialize};
 use serde_json::Value as JsonValue;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+use std::ops::Range;
 use thiserror::Error;
 
 use crate::{
     ast::{
-        expr::Expr,
-        op::{LogicalOp, Op},
+        expr::{Expr, NodePath, NodeStep, PathSegment},
+        op::{LogicalOp, Op, UnaryOp},
     },
+    evaluator::limits::{enforce_length, Exceeded, Limits, Size},
     Value,
 };
 
@@ -14,16 +20,273 @@ use crate::{
 pub enum ParserError {
     #[error("Invalid expr: {0}")]
     InvalidExpr(String),
+    #[error("Invalid syntax: {0}")]
+    InvalidSyntax(Box<SyntaxError>),
     #[error("Invalid value: {0}")]
     InvalidValue(String),
     #[error("Invalid op: {0}")]
     InvalidOp(String),
     #[error("Missing value: {0}")]
     MissingValue(&'static str),
+    #[error("Unserializable expr: {0}")]
+    UnserializableExpr(String),
+    #[error("Expression exceeds the limit of {0} bytes")]
+    LengthLimitExceeded(usize),
+    #[error("Expression nesting exceeds the limit of {0}")]
+    DepthLimitExceeded(usize),
+    #[error("Expression exceeds the limit of {0} nodes")]
+    NodeLimitExceeded(usize),
+}
+
+impl From<Exceeded> for ParserError {
+    fn from(e: Exceeded) -> Self {
+        match e {
+            Exceeded::Length(max) => ParserError::LengthLimitExceeded(max),
+            Exceeded::Depth(max) => ParserError::DepthLimitExceeded(max),
+            Exceeded::Nodes(max) => ParserError::NodeLimitExceeded(max),
+        }
+    }
+}
+
+/// Syntax error in an expression string.
+///
+/// `line` and `column` are 1-based, `column` counting characters; `span` is in bytes.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SyntaxError {
+    pub message: String,
+    pub span: Range<usize>,
+    pub line: usize,
+    pub column: usize,
+    pub token: Option<String>,
+    pub expected: Vec<String>,
+    /// Line of the expression with a caret under the problem
+    pub snippet: String,
+}
+
+impl SyntaxError {
+    fn new(
+        input: &str,
+        message: &str,
+        span: Range<usize>,
+        token: Option<String>,
+        expected: Vec<String>,
+    ) -> Self {
+        let line_start = input[..span.start].rfind('\n').map_or(0, |i| i + 1);
+        let line_end = input[span.start..]
+            .find('\n')
+            .map_or(input.len(), |i| span.start + i);
+        let column = input[line_start..span.start].chars().count() + 1;
+        let width = input[span.start..span.end.min(line_end)].chars().count();
+        let snippet = format!(
+            "{}\n{}{}",
+            &input[line_start..line_end],
+            " ".repeat(column - 1),
+            "^".repeat(width.max(1))
+        );
+
+        SyntaxError {
+            message: message.to_owned(),
+            line: input[..span.start].matches('\n').count() + 1,
+            column,
+            span,
+            token,
+            expected,
+            snippet,
+        }
+    }
+
+    fn from_parse_error(
+        input: &str,
+        error: ParseError<usize, grammar::Token<'_>, GrammarError>,
+    ) -> Self {
+        match error {
+            ParseError::InvalidToken { location } => {
+                let len = input[location..].chars().next().map_or(0, char::len_utf8);
+                let token = input[location..location + len].to_owned();
+                SyntaxError::new(
+                    input,
+                    "invalid token",
+                    location..location + len,
+                    Some(token),
+                    vec![],
+                )
+            }
+            ParseError::UnrecognizedEof { location, expected } => SyntaxError::new(
+                input,
+                "unexpected end of input",
+                location..location,
+                None,
+                describe_expected(expected),
+            ),
+            ParseError::UnrecognizedToken {
+                token: (l, _, r),
+                expected,
+            } => SyntaxError::new(
+                input,
+                "unexpected token",
+                l..r,
+                Some(input[l..r].to_owned()),
+                describe_expected(expected),
+            ),
+            ParseError::ExtraToken { token: (l, _, r) } => SyntaxError::new(
+                input,
+                "extra token",
+                l..r,
+                Some(input[l..r].to_owned()),
+                vec![],
+            ),
+            ParseError::User {
+                error: GrammarError::Literal(l, message, r),
+            } => SyntaxError::new(input, message, l..r, Some(input[l..r].to_owned()), vec![]),
+            ParseError::User {
+                error: GrammarError::Limit(_),
+            } => unreachable!("exceeded limits are reported by parse_expr"),
+        }
+    }
+}
+
+impl Display for SyntaxError {
+    fn fmt(&self, fmt: &mut Formatter) -> std::fmt::Result {
+        write!(fmt, "{}", self.message)?;
+        if let Some(token) = &self.token {
+            write!(fmt, " `{}`", token)?;
+        }
+        write!(fmt, " at {}:{}", self.line, self.column)?;
+        match self.expected.as_slice() {
+            [] => {}
+            [expected] => write!(fmt, ", expected {}", expected)?,
+            expected => write!(fmt, ", expected one of {}", expected.join(", "))?,
+        }
+        write!(fmt, "\n{}", self.snippet)
+    }
+}
+
+/// Readable names for the terminals expected by the grammar; regex terminals are named after
+/// what they match
+fn describe_expected(expected: Vec<String>) -> Vec<String> {
+    let mut names: Vec<String> = vec![];
+    for terminal in expected {
+        let name = match terminal.strip_prefix("r#\"") {
+            Some(regex) if regex.trim_start_matches('\\').starts_with('{') => {
+                "identifier".to_owned()
+            }
+            Some(regex) if regex.starts_with("[0-9]") => "number".to_owned(),
+            Some(regex) if regex.starts_with('\'') || regex.starts_with("\\\"") => {
+                "string".to_owned()
+            }
+            Some(regex) if regex.starts_with("[a-z_]") => "function".to_owned(),
+            _ => terminal,
+        };
+        if !names.contains(&name) {
+            names.push(name);
+        }
+    }
+    names
+}
+
+/// Error raised by a grammar action: an invalid string or number literal, with its start
+/// offset, message and end offset, or an exceeded limit
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum GrammarError {
+    Literal(usize, &'static str, usize),
+    Limit(Exceeded),
+}
+
+/// State of the grammar while it builds an expression
+#[derive(Debug, Default)]
+pub(crate) struct Builder {
+    size: RefCell<Size>,
+    /// Levels of nodes up to and including each operand not yet used by an expression
+    depths: RefCell<Vec<usize>>,
+    /// Spans of the nodes in the order they are built, only collected for a source map
+    spans: Option<RefCell<Vec<Range<usize>>>>,
+}
+
+impl Builder {
+    fn new(limits: &Limits) -> Self {
+        Builder {
+            size: RefCell::new(Size::new(limits)),
+            ..Builder::default()
+        }
+    }
+}
+
+/// Build an expression node, recording its span and failing as soon as it crosses a limit.
+///
+/// The grammar builds every node right after its operands, so they are the last entries of
+/// `depths`.
+pub(crate) fn node<T>(
+    builder: &Builder,
+    l: usize,
+    r: usize,
+    expr: Expr,
+) -> Result<Box<Expr>, ParseError<usize, T, GrammarError>> {
+    let limit = |e| ParseError::User {
+        error: GrammarError::Limit(e),
+    };
+    let mut size = builder.size.borrow_mut();
+    let mut depths = builder.depths.borrow_mut();
+    let operands = match &expr {
+        Expr::Identifier(_) | Expr::Path(_, _) | Expr::Value(_) => 0,
+        Expr::Op(_, _, _) => 2,
+        Expr::Unary(_, _) => 1,
+        Expr::Call(_, args) => args.len(),
+        Expr::Conditional(_, _, _) => 3,
+    };
+    let depth = match &expr {
+        Expr::Value(v) => size.elements(v).map_err(limit)? + 1,
+        _ => {
+            let first = depths.len() - operands;
+            depths.drain(first..).max().unwrap_or(0) + 1
+        }
+    };
+    size.node(depth).map_err(limit)?;
+    depths.push(depth);
+
+    if let Some(spans) = &builder.spans {
+        spans.borrow_mut().push(l..r);
+    }
+    Ok(Box::new(expr))
+}
+
+/// Build a unary node, folding a negated number literal into the literal so that `-5` parses
+/// to the same node that displays as `-5`.
+///
+/// The literal is the last node built, so it keeps its depth and count and only takes the
+/// span of the whole expression.
+pub(crate) fn unary<T>(
+    builder: &Builder,
+    l: usize,
+    r: usize,
+    op: UnaryOp,
+    expr: Box<Expr>,
+) -> Result<Box<Expr>, ParseError<usize, T, GrammarError>> {
+    match (op, &*expr) {
+        (UnaryOp::Neg, Expr::Value(Value::Number(n))) => {
+            if let Some(spans) = &builder.spans {
+                if let Some(span) = spans.borrow_mut().last_mut() {
+                    *span = l..r;
+                }
+            }
+            Ok(Box::new(Expr::Value(Value::Number(-n))))
+        }
+        _ => node(builder, l, r, Expr::Unary(op, expr)),
+    }
 }
 
 lalrpop_mod!(pub grammar, "/parser/grammar.rs");
 
+/// Byte spans of the subexpressions of a parsed expression string, by node path.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SourceMap(HashMap<NodePath, Range<usize>>);
+
+impl SourceMap {
+    /// Span of the subexpression at `path`
+    pub fn span(&self, path: &NodePath) -> Option<Range<usize>> {
+        self.0.get(path).cloned()
+    }
+}
+
 /// Parse expression from str
 ///
 /// # Examples
@@ -38,10 +301,113 @@ lalrpop_mod!(pub grammar, "/parser/grammar.rs");
 /// assert_eq!(rs, Value::from(9));
 /// ```
 pub fn parse_expr_from_str(expr_str: &str) -> Result<Box<Expr>, ParserError> {
-    match grammar::ExprParser::new().parse(expr_str) {
-        Ok(v) => Ok(v),
-        Err(e) => Err(ParserError::InvalidExpr(e.to_string())),
+    parse_expr(&Builder::default(), expr_str)
+}
+
+/// Parse expression from str, rejecting expressions longer, nested deeper or with more nodes
+/// than `limits` allow.
+///
+/// The length is checked before parsing, depth and nodes as the expression is built.
+///
+/// # Examples
+///
+/// ```
+/// use evaluator_rs::*;
+///
+/// let limits = Limits::new().with_max_depth(3);
+/// assert!(parse_expr_from_str_with_limits("{a} + 1", &limits).is_ok());
+/// assert_eq!(
+///     parse_expr_from_str_with_limits("-(-(-{a}))", &limits),
+///     Err(ParserError::DepthLimitExceeded(3))
+/// );
+/// ```
+pub fn parse_expr_from_str_with_limits(
+    expr_str: &str,
+    limits: &Limits,
+) -> Result<Box<Expr>, ParserError> {
+    enforce_length(expr_str, limits)?;
+    parse_expr(&Builder::new(limits), expr_str)
+}
+
+/// Parse expression from str along with the source span of every subexpression
+///
+/// # Examples
+///
+/// ```
+/// use evaluator_rs::*;
+/// use std::collections::HashMap;
+///
+/// let (expr, source_map) = parse_expr_with_source_map("{a} > 1 && {b} + 1 > 2").unwrap();
+/// let parameters = HashMap::from([("a", Value::from(2)), ("b", Value::from("x"))]);
+/// let error = evaluate(&expr, &parameters).unwrap_err();
+/// assert_eq!(error.path.to_string(), "/rhs/lhs");
+/// assert_eq!(source_map.span(&error.path), Some(11..18));
+/// ```
+pub fn parse_expr_with_source_map(expr_str: &str) -> Result<(Box<Expr>, SourceMap), ParserError> {
+    let builder = Builder {
+        spans: Some(RefCell::default()),
+        ..Builder::default()
+    };
+    let expr = parse_expr(&builder, expr_str)?;
+
+    // the grammar builds every node after its operands, so spans are in post-order
+    let source_map = post_order_paths(&expr)
+        .into_iter()
+        .zip(builder.spans.unwrap_or_default().into_inner())
+        .collect();
+
+    Ok((expr, SourceMap(source_map)))
+}
+
+fn parse_expr(builder: &Builder, expr_str: &str) -> Result<Box<Expr>, ParserError> {
+    grammar::ExprParser::new()
+        .parse(builder, expr_str)
+        .map_err(|e| match e {
+            ParseError::User {
+                error: GrammarError::Limit(e),
+            } => e.into(),
+            e => ParserError::InvalidSyntax(Box::new(SyntaxError::from_parse_error(expr_str, e))),
+        })
+}
+
+/// Paths of all subexpressions, operands before the expressions using them
+fn post_order_paths(expr: &Expr) -> Vec<NodePath> {
+    let mut paths = vec![];
+    let mut stack = vec![(expr, NodePath::default(), false)];
+
+    while let Some((expr, path, visited)) = stack.pop() {
+        if visited {
+            paths.push(path);
+            continue;
+        }
+
+        let children: Vec<(&Expr, NodeStep)> = match expr {
+            Expr::Identifier(_) | Expr::Path(_, _) | Expr::Value(_) => {
+                vec![]
+            }
+            Expr::Op(lhs, _, rhs) => vec![(lhs, NodeStep::Lhs), (rhs, NodeStep::Rhs)],
+            Expr::Unary(_, rhs) => vec![(rhs, NodeStep::Rhs)],
+            Expr::Call(_, args) => args
+                .iter()
+                .enumerate()
+                .map(|(i, arg)| (arg, NodeStep::Arg(i)))
+                .collect(),
+            Expr::Conditional(cond, then, otherwise) => vec![
+                (cond, NodeStep::If),
+                (then, NodeStep::Then),
+                (otherwise, NodeStep::Else),
+            ],
+        };
+
+        stack.push((expr, path.clone(), true));
+        for (child, step) in children.into_iter().rev() {
+            let mut child_path = path.clone();
+            child_path.0.push(step);
+            stack.push((child, child_path, false));
+        }
     }
+
+    paths
 }
 
 /// Parse expression from json
@@ -64,17 +430,95 @@ pub fn parse_expr_from_str(expr_str: &str) -> Result<Box<Expr>, ParserError> {
 /// ```
 pub fn parse_expr_from_json(expr_str: &str) -> Result<Box<Expr>, ParserError> {
     match serde_json::from_str::<serde_json::Value>(expr_str) {
-        Ok(value) => parse_expr_from_json_value(&value),
+        Ok(value) => parse_expr_from_json_value(&value, &Limits::default()),
         Err(e) => Err(ParserError::InvalidExpr(e.to_string())),
     }
 }
 
-fn parse_expr_from_json_value(expr_json: &serde_json::Value) -> Result<Box<Expr>, ParserError> {
+/// Parse expression from json, rejecting expressions longer, nested deeper or with more nodes
+/// than `limits` allow
+pub fn parse_expr_from_json_with_limits(
+    expr_str: &str,
+    limits: &Limits,
+) -> Result<Box<Expr>, ParserError> {
+    enforce_length(expr_str, limits)?;
+    match serde_json::from_str::<serde_json::Value>(expr_str) {
+        Ok(value) => parse_expr_from_json_value(&value, limits),
+        Err(e) => Err(ParserError::InvalidExpr(e.to_string())),
+    }
+}
+
+/// Serialize expression to json, the inverse of `parse_expr_from_json`.
+///
+/// Values that would not be read back as written, such as objects or strings shaped like an
+/// identifier, are reported as `ParserError::UnserializableExpr`.
+///
+/// # Examples
+///
+/// ```
+/// use evaluator_rs::*;
+///
+/// let expr = parse_expr_from_str("{a} in [4, 5, 6]").unwrap();
+/// let json_expr = expr_to_json(&expr).unwrap();
+/// assert_eq!(json_expr, r#"{"lhs":"{a}","op":"in","rhs":[4,5,6]}"#);
+/// assert_eq!(parse_expr_from_json(&json_expr).unwrap(), expr);
+/// ```
+pub fn expr_to_json(expr: &Expr) -> Result<String, ParserError> {
+    expr.to_json().map_err(ParserError::UnserializableExpr)
+}
+
+/// Parse expression from a json value, failing as soon as a node crosses `limits`
+pub(crate) fn parse_expr_from_json_value(
+    expr_json: &serde_json::Value,
+    limits: &Limits,
+) -> Result<Box<Expr>, ParserError> {
+    json_node(expr_json, &mut Size::new(limits), 1)
+}
+
+fn json_node(
+    expr_json: &serde_json::Value,
+    size: &mut Size,
+    depth: usize,
+) -> Result<Box<Expr>, ParserError> {
+    if !expr_json.is_array() {
+        size.node(depth)?;
+    }
+
     match expr_json {
+        JsonValue::Object(v) if v.contains_key("if") => {
+            let then = expr_json
+                .get("then")
+                .ok_or(ParserError::MissingValue("then"))?;
+            let otherwise = expr_json
+                .get("else")
+                .ok_or(ParserError::MissingValue("else"))?;
+
+            Ok(Box::new(Expr::Conditional(
+                json_node(&expr_json["if"], size, depth + 1)?,
+                json_node(then, size, depth + 1)?,
+                json_node(otherwise, size, depth + 1)?,
+            )))
+        }
+        JsonValue::Object(v) if v.contains_key("call") => {
+            let name = &expr_json["call"];
+            let name = name
+                .as_str()
+                .ok_or_else(|| ParserError::InvalidValue(name.to_string()))?;
+            let args = expr_json
+                .get("args")
+                .ok_or(ParserError::MissingValue("args"))?;
+            let args = args
+                .as_array()
+                .ok_or_else(|| ParserError::InvalidValue(args.to_string()))?;
+
+            let args = args
+                .iter()
+                .map(|arg| json_node(arg, size, depth + 1).map(|arg| *arg))
+                .collect::<Result<Vec<_>, _>>()?;
+
+            Ok(Box::new(Expr::Call(name.to_owned(), args)))
+        }
         JsonValue::Object(v) => {
-            let lhs = expr_json
-                .get("lhs")
-                .ok_or(ParserError::MissingValue("lhs"))?;
             let op = expr_json.get("op").ok_or(ParserError::MissingValue("op"))?;
             let rhs = expr_json
                 .get("rhs")
@@ -84,24 +528,117 @@ fn parse_expr_from_json_value(expr_json: &serde_json::Value) -> Result<Box<Expr>
                 .as_str()
                 .ok_or_else(|| ParserError::InvalidOp(op.to_string()))?;
 
-            let op = match grammar::OpParser::new().parse(op) {
+            // Without lhs the object describes a prefix operator applied to rhs.
+            let lhs = match expr_json.get("lhs") {
+                Some(lhs) => lhs,
+                None => {
+                    let op = match grammar::UnaryOpParser::new().parse(&Builder::default(), op) {
+                        Ok(v) => v,
+                        // a binary operator is only missing its left operand
+                        Err(_)
+                            if grammar::OpParser::new()
+                                .parse(&Builder::default(), op)
+                                .is_ok() =>
+                        {
+                            return Err(ParserError::MissingValue("lhs"))
+                        }
+                        Err(_) => return Err(ParserError::InvalidOp(op.to_owned())),
+                    };
+
+                    return Ok(Box::new(Expr::Unary(op, json_node(rhs, size, depth + 1)?)));
+                }
+            };
+
+            let op = match grammar::OpParser::new().parse(&Builder::default(), op) {
                 Ok(v) => v,
-                Err(e) => return Err(ParserError::InvalidOp(op.to_owned())),
+                Err(_) => return Err(ParserError::InvalidOp(op.to_owned())),
             };
 
-            let lhs = parse_expr_from_json_value(lhs)?;
-            let rhs = parse_expr_from_json_value(rhs)?;
+            let lhs = json_node(lhs, size, depth + 1)?;
+            let rhs = json_node(rhs, size, depth + 1)?;
 
             Ok(Box::new(Expr::Op(lhs, op, rhs)))
         }
-        JsonValue::String(v) => match grammar::IdentifierParser::new().parse(v) {
-            Ok(var) => Ok(Box::new(Expr::Identifier(parse_parameter_name(&var)))),
-            Err(_) => Ok(Box::new(Expr::Value(Value::from(v.as_str())))),
-        },
-        JsonValue::Null => Err(ParserError::InvalidValue("null".to_owned())),
-        v => Ok(Box::new(Expr::Value(Value::from(v)))),
+        JsonValue::String(v) => {
+            match grammar::IdentifierParser::new().parse(&Builder::default(), v) {
+                Ok(var) => parse_identifier(&var)
+                    .map(Box::new)
+                    .map_err(|e| ParserError::InvalidExpr(format!("{}: {}", e, v))),
+                Err(_) => Ok(Box::new(Expr::Value(Value::from(v.as_str())))),
+            }
+        }
+        v => {
+            let value = Value::from(v);
+            let levels = size.elements(&value)?;
+            size.node(depth + levels)?;
+            Ok(Box::new(Expr::Value(value)))
+        }
+    }
+}
+/// Build an identifier or nested path expression from a `{...}` token
+pub fn parse_identifier(value: &str) -> Result<Expr, &'static str> {
+    let name = parse_parameter_name(value);
+    let split = match name.find(['.', '[']) {
+        Some(v) => v,
+        None => return Ok(Expr::Identifier(name)),
+    };
+
+    let mut segments = vec![];
+    let mut rest = &name[split..];
+    while !rest.is_empty() {
+        let end = rest[1..].find(['.', '[']).map_or(rest.len(), |v| v + 1);
+        let segment = match rest.as_bytes()[0] {
+            b'.' => PathSegment::Field(rest[1..end].to_owned()),
+            _ => PathSegment::Index(
+                rest[1..end - 1]
+                    .parse()
+                    .map_err(|_| "path index out of range")?,
+            ),
+        };
+        segments.push(segment);
+        rest = &rest[end..];
+    }
+
+    Ok(Expr::Path(name[..split].to_owned(), segments))
+}
+
+/// Unquote a `'...'` or `"..."` literal and resolve its escape sequences.
+/// Unknown escapes such as `\d` are rejected, so a backslash is always written `\\`.
+pub fn parse_string_literal(value: &str) -> Result<String, &'static str> {
+    let mut rs = String::with_capacity(value.len());
+    let mut chars = value[1..value.len() - 1].chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            rs.push(c);
+            continue;
+        }
+
+        match chars.next() {
+            Some('n') => rs.push('\n'),
+            Some('r') => rs.push('\r'),
+            Some('t') => rs.push('\t'),
+            Some('0') => rs.push('\0'),
+            Some(c @ ('\\' | '\'' | '"')) => rs.push(c),
+            Some('u') => {
+                let code = chars.as_str();
+                let end = match (code.starts_with('{'), code.find('}')) {
+                    (true, Some(end)) => end,
+                    _ => return Err("invalid unicode escape"),
+                };
+                let c = u32::from_str_radix(&code[1..end], 16)
+                    .ok()
+                    .and_then(char::from_u32)
+                    .ok_or("invalid unicode escape")?;
+                rs.push(c);
+                chars = code[end + 1..].chars();
+            }
+            Some(_) | None => return Err("invalid escape"),
+        }
     }
+
+    Ok(rs)
 }
+
 pub fn parse_parameter_name(value: &str) -> String {
     let mut chars = value.chars();
     chars.next();
@@ -113,6 +650,7 @@ pub fn parse_parameter_name(value: &str) -> String {
 mod tests {
     use super::*;
     use crate::ast::op::*;
+    use crate::test_util::{chain, PASS_DEPTH};
     use crate::Expr;
     use crate::Value;
 
@@ -249,13 +787,265 @@ mod tests {
                 }"#,
                 want: Err(ParserError::InvalidOp("add".to_owned())),
             },
+            TestCase {
+                expr: r#"
+                {
+                    "lhs": "{coupon}",
+                    "op": "!=",
+                    "rhs": null
+                }"#,
+                want: Ok(Box::new(Expr::Op(
+                    Box::new(Expr::Identifier("coupon".to_owned())),
+                    Op::Relational(RelationalOp::Neq),
+                    Box::new(Expr::Value(Value::Null)),
+                ))),
+            },
+            TestCase {
+                expr: r#"
+                {
+                    "lhs": "{user.address.city}",
+                    "op": "==",
+                    "rhs": "Hanoi"
+                }"#,
+                want: Ok(Box::new(Expr::Op(
+                    Box::new(Expr::Path(
+                        "user".to_owned(),
+                        vec![
+                            PathSegment::Field("address".to_owned()),
+                            PathSegment::Field("city".to_owned()),
+                        ],
+                    )),
+                    Op::Relational(RelationalOp::Eq),
+                    Box::new(Expr::Value(Value::from("Hanoi"))),
+                ))),
+            },
+            TestCase {
+                expr: r#"
+                {
+                    "lhs": {
+                        "call": "len",
+                        "args": ["{name}"]
+                    },
+                    "op": ">",
+                    "rhs": {
+                        "call": "max",
+                        "args": [1, [2, 3]]
+                    }
+                }"#,
+                want: Ok(Box::new(Expr::Op(
+                    Box::new(Expr::Call(
+                        "len".to_owned(),
+                        vec![Expr::Identifier("name".to_owned())],
+                    )),
+                    Op::Relational(RelationalOp::Gt),
+                    Box::new(Expr::Call(
+                        "max".to_owned(),
+                        vec![
+                            Expr::Value(Value::from(1)),
+                            Expr::Value(Value::Array(vec![Value::from(2), Value::from(3)])),
+                        ],
+                    )),
+                ))),
+            },
+            TestCase {
+                expr: r#"
+                {
+                    "lhs": "{email}",
+                    "op": "matches",
+                    "rhs": "^[a-z]+@example\\.com$"
+                }"#,
+                want: Ok(Box::new(Expr::Op(
+                    Box::new(Expr::Identifier("email".to_owned())),
+                    Op::Relational(RelationalOp::Matches),
+                    Box::new(Expr::Value(Value::from(r"^[a-z]+@example\.com$"))),
+                ))),
+            },
+            TestCase {
+                expr: r#"
+                {
+                    "if": {
+                        "lhs": "{tier}",
+                        "op": "==",
+                        "rhs": "gold"
+                    },
+                    "then": 0.2,
+                    "else": "{default_rate}"
+                }"#,
+                want: Ok(Box::new(Expr::Conditional(
+                    Box::new(Expr::Op(
+                        Box::new(Expr::Identifier("tier".to_owned())),
+                        Op::Relational(RelationalOp::Eq),
+                        Box::new(Expr::Value(Value::from("gold"))),
+                    )),
+                    Box::new(Expr::Value(Value::from(0.2))),
+                    Box::new(Expr::Identifier("default_rate".to_owned())),
+                ))),
+            },
+            TestCase {
+                expr: r#"
+                {
+                    "if": true,
+                    "then": 1
+                }"#,
+                want: Err(ParserError::MissingValue("else")),
+            },
+            TestCase {
+                expr: r#"
+                {
+                    "call": "len"
+                }"#,
+                want: Err(ParserError::MissingValue("args")),
+            },
+            TestCase {
+                expr: r#"
+                {
+                    "op": "!",
+                    "rhs": "{banned}"
+                }"#,
+                want: Ok(Box::new(Expr::Unary(
+                    UnaryOp::Not,
+                    Box::new(Expr::Identifier("banned".to_owned())),
+                ))),
+            },
+            TestCase {
+                expr: r#"
+                {
+                    "lhs": {
+                        "op": "-",
+                        "rhs": "{delta}"
+                    },
+                    "op": ">",
+                    "rhs": 3
+                }"#,
+                want: Ok(Box::new(Expr::Op(
+                    Box::new(Expr::Unary(
+                        UnaryOp::Neg,
+                        Box::new(Expr::Identifier("delta".to_owned())),
+                    )),
+                    Op::Relational(RelationalOp::Gt),
+                    Box::new(Expr::Value(Value::from(3))),
+                ))),
+            },
+            TestCase {
+                expr: r#"
+                {
+                    "op": "+",
+                    "rhs": 4
+                }"#,
+                want: Err(ParserError::MissingValue("lhs")),
+            },
+            TestCase {
+                expr: r#"
+                {
+                    "op": "~",
+                    "rhs": true
+                }"#,
+                want: Err(ParserError::InvalidOp("~".to_owned())),
+            },
         ];
         test_cases.iter().for_each(|case| {
             let expr = parse_expr_from_json(case.expr);
             assert_eq!(case.want, expr);
+
+            if let Ok(expr) = expr {
+                let json_expr = expr_to_json(&expr).unwrap();
+                assert_eq!(parse_expr_from_json(&json_expr), Ok(expr), "{}", json_expr);
+            }
         });
     }
 
+    #[test]
+    fn test_expr_to_json() {
+        let expr = parse_expr_from_str(
+            "if !{banned} && len({user.tags[0]}) > 2.5 then 'ok' else max(1, -{a})",
+        )
+        .unwrap();
+        assert_eq!(
+            serde_json::from_str::<JsonValue>(&expr_to_json(&expr).unwrap()).unwrap(),
+            serde_json::json!({
+                "if": {
+                    "lhs": { "op": "!", "rhs": "{banned}" },
+                    "op": "&&",
+                    "rhs": {
+                        "lhs": { "call": "len", "args": ["{user.tags[0]}"] },
+                        "op": ">",
+                        "rhs": 2.5
+                    }
+                },
+                "then": "ok",
+                "else": {
+                    "call": "max",
+                    "args": [1, { "op": "-", "rhs": "{a}" }]
+                }
+            })
+        );
+        assert_eq!(
+            parse_expr_from_json(&expr_to_json(&expr).unwrap()).unwrap(),
+            expr
+        );
+
+        let expr = Expr::Op(
+            Box::new(Expr::Value(Value::Array(vec![Value::from(
+                &serde_json::json!({ "a": 1 }),
+            )]))),
+            Op::Relational(RelationalOp::Eq),
+            Box::new(Expr::Value(Value::Null)),
+        );
+        assert_eq!(
+            expr_to_json(&expr).unwrap(),
+            r#"{"lhs":[{"a":1}],"op":"==","rhs":null}"#
+        );
+
+        assert_eq!(
+            expr_to_json(&Expr::Value(Value::from(&serde_json::json!({ "a": 1 })))),
+            Err(ParserError::UnserializableExpr(
+                "object value {a: 1} has no JSON expression form".to_owned()
+            ))
+        );
+        assert_eq!(
+            expr_to_json(&Expr::Value(Value::from("{a}"))),
+            Err(ParserError::UnserializableExpr(
+                "string {a} would be read as an identifier".to_owned()
+            ))
+        );
+    }
+
+    #[test]
+    fn test_expr_to_json_deep_expr() {
+        let expr = parse_expr_from_str(&chain("{a}", PASS_DEPTH)).unwrap();
+        assert_eq!(
+            expr_to_json(&expr).unwrap(),
+            format!(
+                "{}\"{{a}}\"{}",
+                r#"{"lhs":"#.repeat(PASS_DEPTH - 1),
+                r#","op":"||","rhs":"{a}"}"#.repeat(PASS_DEPTH - 1)
+            )
+        );
+
+        let mut expr = Expr::Identifier("a".to_owned());
+        for _ in 0..PASS_DEPTH / 2 {
+            expr = Expr::Conditional(
+                Box::new(Expr::Unary(UnaryOp::Not, Box::new(expr))),
+                Box::new(Expr::Value(Value::Null)),
+                Box::new(Expr::Call(
+                    "max".to_owned(),
+                    vec![
+                        Expr::Identifier("b".to_owned()),
+                        Expr::Value(Value::from(1)),
+                    ],
+                )),
+            );
+        }
+        assert_eq!(
+            expr_to_json(&expr).unwrap(),
+            format!(
+                "{}\"{{a}}\"{}",
+                r#"{"if":{"op":"!","rhs":"#.repeat(PASS_DEPTH / 2),
+                r#"},"then":null,"else":{"call":"max","args":["{b}",1]}}"#.repeat(PASS_DEPTH / 2)
+            )
+        );
+    }
+
     #[test]
     fn test_parse_expr() {
         assert_eq!(
@@ -267,9 +1057,390 @@ mod tests {
             )
         );
 
+        assert_eq!(
+            *parse_expr_from_str("-5 + 2").unwrap(),
+            Expr::Op(
+                Box::new(Expr::Value(Value::from(-5))),
+                Op::Additive(AdditiveOp::Add),
+                Box::new(Expr::Value(Value::from(2))),
+            )
+        );
+
+        assert_eq!(
+            *parse_expr_from_str("!{banned} && {active}").unwrap(),
+            Expr::Op(
+                Box::new(Expr::Unary(
+                    UnaryOp::Not,
+                    Box::new(Expr::Identifier("banned".to_owned()))
+                )),
+                Op::Logical(LogicalOp::And),
+                Box::new(Expr::Identifier("active".to_owned())),
+            )
+        );
+
+        assert_eq!(
+            *parse_expr_from_str("{a} == null").unwrap(),
+            Expr::Op(
+                Box::new(Expr::Identifier("a".to_owned())),
+                Op::Relational(RelationalOp::Eq),
+                Box::new(Expr::Value(Value::Null)),
+            )
+        );
+
+        assert_eq!(
+            *parse_expr_from_str("{user.age} >= {limits[0]}").unwrap(),
+            Expr::Op(
+                Box::new(Expr::Path(
+                    "user".to_owned(),
+                    vec![PathSegment::Field("age".to_owned())]
+                )),
+                Op::Relational(RelationalOp::Gte),
+                Box::new(Expr::Path("limits".to_owned(), vec![PathSegment::Index(0)])),
+            )
+        );
+
+        assert_eq!(
+            *parse_expr_from_str("round(abs({a}), 2) > min(1, 2)").unwrap(),
+            Expr::Op(
+                Box::new(Expr::Call(
+                    "round".to_owned(),
+                    vec![
+                        Expr::Call("abs".to_owned(), vec![Expr::Identifier("a".to_owned())]),
+                        Expr::Value(Value::from(2)),
+                    ]
+                )),
+                Op::Relational(RelationalOp::Gt),
+                Box::new(Expr::Call(
+                    "min".to_owned(),
+                    vec![Expr::Value(Value::from(1)), Expr::Value(Value::from(2))]
+                )),
+            )
+        );
+
+        assert_eq!(
+            *parse_expr_from_str("{a} in index([1])").unwrap(),
+            Expr::Op(
+                Box::new(Expr::Identifier("a".to_owned())),
+                Op::Relational(RelationalOp::In),
+                Box::new(Expr::Call(
+                    "index".to_owned(),
+                    vec![Expr::Value(Value::Array(vec![Value::from(1)]))]
+                )),
+            )
+        );
+
+        assert_eq!(
+            *parse_expr_from_str("if {a} > 1 && {b} then 1 else if {c} then 2 else 3").unwrap(),
+            Expr::Conditional(
+                Box::new(Expr::Op(
+                    Box::new(Expr::Op(
+                        Box::new(Expr::Identifier("a".to_owned())),
+                        Op::Relational(RelationalOp::Gt),
+                        Box::new(Expr::Value(Value::from(1))),
+                    )),
+                    Op::Logical(LogicalOp::And),
+                    Box::new(Expr::Identifier("b".to_owned())),
+                )),
+                Box::new(Expr::Value(Value::from(1))),
+                Box::new(Expr::Conditional(
+                    Box::new(Expr::Identifier("c".to_owned())),
+                    Box::new(Expr::Value(Value::from(2))),
+                    Box::new(Expr::Value(Value::from(3))),
+                )),
+            )
+        );
+
+        let expr = parse_expr_from_str("(if {vip} then 0.2 else 0.1) * {price} > 10").unwrap();
+        assert_eq!(*parse_expr_from_str(&expr.to_string()).unwrap(), *expr);
+        assert!(parse_expr_from_str("if {a} then 1").is_err());
+
+        assert_eq!(
+            *parse_expr_from_str(r#"{name} in ['it\'s', "say \"hi\"\n"]"#).unwrap(),
+            Expr::Op(
+                Box::new(Expr::Identifier("name".to_owned())),
+                Op::Relational(RelationalOp::In),
+                Box::new(Expr::Value(Value::Array(vec![
+                    Value::from("it's"),
+                    Value::from("say \"hi\"\n"),
+                ]))),
+            )
+        );
+
+        let expr = parse_expr_from_str(r#"{a} == 'x\'y\\z\n' || {b} == "\u{1F600}\t""#).unwrap();
+        assert_eq!(*parse_expr_from_str(&expr.to_string()).unwrap(), *expr);
+        assert_eq!(
+            parse_expr_from_str(r"'\u{zz}'").unwrap_err(),
+            ParserError::InvalidSyntax(Box::new(SyntaxError {
+                message: "invalid unicode escape".to_owned(),
+                span: 0..8,
+                line: 1,
+                column: 1,
+                token: Some(r"'\u{zz}'".to_owned()),
+                expected: vec![],
+                snippet: "'\\u{zz}'\n^^^^^^^^".to_owned(),
+            }))
+        );
+
+        assert!(parse_expr_from_str("{user.} > 1").is_err());
+        assert!(parse_expr_from_str("{items[a]} > 1").is_err());
+
         assert_eq!(
             parse_expr_from_str("a + 2").unwrap_err(),
-            ParserError::InvalidExpr("Invalid token at 0".to_string()),
+            ParserError::InvalidSyntax(Box::new(SyntaxError {
+                message: "unexpected token".to_owned(),
+                span: 2..3,
+                line: 1,
+                column: 3,
+                token: Some("+".to_owned()),
+                expected: vec!["\"(\"".to_owned()],
+                snippet: "a + 2\n  ^".to_owned(),
+            })),
+        );
+    }
+
+    #[test]
+    fn test_source_map() {
+        let source = "if {a} > 1 then max({b.c}, 2) else -({d} * 3)";
+        let (expr, source_map) = parse_expr_with_source_map(source).unwrap();
+        assert_eq!(*expr, *parse_expr_from_str(source).unwrap());
+
+        let span = |steps: Vec<NodeStep>| {
+            let range = source_map.span(&NodePath(steps)).unwrap();
+            &source[range]
+        };
+        assert_eq!(span(vec![]), source);
+        assert_eq!(span(vec![NodeStep::If]), "{a} > 1");
+        assert_eq!(span(vec![NodeStep::If, NodeStep::Rhs]), "1");
+        assert_eq!(span(vec![NodeStep::Then]), "max({b.c}, 2)");
+        assert_eq!(span(vec![NodeStep::Then, NodeStep::Arg(0)]), "{b.c}");
+        assert_eq!(span(vec![NodeStep::Else]), "-({d} * 3)");
+        assert_eq!(span(vec![NodeStep::Else, NodeStep::Rhs]), "{d} * 3");
+        assert_eq!(
+            span(vec![NodeStep::Else, NodeStep::Rhs, NodeStep::Lhs]),
+            "{d}"
+        );
+        assert_eq!(source_map.span(&NodePath(vec![NodeStep::Lhs])), None);
+
+        let source = "{a} - -(2)";
+        let (_, source_map) = parse_expr_with_source_map(source).unwrap();
+        let range = source_map.span(&NodePath(vec![NodeStep::Rhs])).unwrap();
+        assert_eq!(&source[range], "-(2)");
+    }
+
+    #[test]
+    fn test_syntax_error() {
+        let syntax_error = |s| match parse_expr_from_str(s) {
+            Err(ParserError::InvalidSyntax(e)) => *e,
+            r => panic!("expected syntax error for {}, got {:?}", s, r),
+        };
+
+        let e = syntax_error("{a} > ");
+        assert_eq!((e.span, e.line, e.column, e.token), (5..5, 1, 6, None));
+        assert_eq!(
+            e.expected,
+            [
+                "string",
+                "number",
+                "function",
+                "identifier",
+                "\"!\"",
+                "\"(\"",
+                "\"-\"",
+                "\"[\"",
+                "\"false\"",
+                "\"null\"",
+                "\"true\""
+            ]
+        );
+
+        let e = syntax_error("{a} $ 1");
+        assert_eq!(e.to_string(), "invalid token `$` at 1:5\n{a} $ 1\n    ^");
+
+        let e = syntax_error("{a} > 1\n  && {b} > 2 2");
+        assert_eq!((e.span, e.line, e.column), (21..22, 2, 14));
+        assert_eq!(e.token.as_deref(), Some("2"));
+        assert_eq!(e.snippet, "  && {b} > 2 2\n             ^");
+
+        let e = syntax_error("{a} matches '\\d+'");
+        assert_eq!(e.message, "invalid escape");
+        assert_eq!(e.span, 12..17);
+
+        let e = syntax_error("{name} == 'é\\n' || 'ü\\u{d800}'");
+        assert_eq!(e.message, "invalid unicode escape");
+        assert_eq!((e.line, e.column), (1, 20));
+        assert_eq!(
+            e.snippet,
+            "{name} == 'é\\n' || 'ü\\u{d800}'\n                   ^^^^^^^^^^^"
+        );
+
+        // integers too large for i64 are still numbers, and print back in a form that parses
+        let expr = parse_expr_from_str("99999999999999999999 > 1").unwrap();
+        assert_eq!(
+            *expr,
+            Expr::Op(
+                Box::new(Expr::Value(Value::from(1e20))),
+                Op::Relational(RelationalOp::Gt),
+                Box::new(Expr::Value(Value::from(1))),
+            )
+        );
+        assert_eq!(parse_expr_from_str(&expr.to_string()).unwrap(), expr);
+        let digits = "9".repeat(400);
+        let e = syntax_error(&digits);
+        assert_eq!(e.message, "number out of range");
+        assert_eq!(e.span, 0..400);
+        let e = syntax_error(&format!("{{a}} > {}.5", "9".repeat(400)));
+        assert_eq!(e.message, "number out of range");
+        assert_eq!(e.span, 6..408);
+    }
+
+    #[test]
+    fn test_parse_with_limits() {
+        let limits = Limits::new().with_max_depth(4).with_max_nodes(8);
+        let test_cases: Vec<(&str, Result<(), ParserError>)> = vec![
+            ("{a} + 1 > 2", Ok(())),
+            ("((((({a}) + 1) > 2)))", Ok(())),
+            ("{a} + 1 + 2 + 3", Ok(())),
+            ("-(-(-(-{a})))", Err(ParserError::DepthLimitExceeded(4))),
+            ("-(-(-(-{a}))) + )", Err(ParserError::DepthLimitExceeded(4))),
+            ("max(1, 2, 3, 4, 5, 6, 7)", Ok(())),
+            (
+                "max(1, 2, 3, 4, 5, 6, 7, 8)",
+                Err(ParserError::NodeLimitExceeded(8)),
+            ),
+            ("{a} in [1, 2, 3, 4, 5]", Ok(())),
+            (
+                "{a} in [1, 2, 3, 4, 5, 6]",
+                Err(ParserError::NodeLimitExceeded(8)),
+            ),
+        ];
+        test_cases.into_iter().for_each(|(s, expected)| {
+            let rs = parse_expr_from_str_with_limits(s, &limits).map(|_| ());
+            assert_eq!(rs, expected, "expr: {}", s);
+        });
+
+        let json_expr = r#"{"lhs": "{a}", "op": "in", "rhs": [[1, 2], 3]}"#;
+        assert!(parse_expr_from_json_with_limits(json_expr, &limits).is_ok());
+        assert_eq!(
+            parse_expr_from_json_with_limits(json_expr, &limits.with_max_depth(3)),
+            Err(ParserError::DepthLimitExceeded(3))
+        );
+        assert_eq!(
+            parse_expr_from_json_with_limits(json_expr, &limits.with_max_nodes(6)),
+            Err(ParserError::NodeLimitExceeded(6))
+        );
+        let deep_json = r#"{"op": "!", "rhs": {"op": "!", "rhs": {"op": "?", "rhs": 1}}}"#;
+        assert_eq!(
+            parse_expr_from_json_with_limits(deep_json, &limits.with_max_depth(2)),
+            Err(ParserError::DepthLimitExceeded(2))
+        );
+
+        let deep = format!("{}{{a}}{}", "-(".repeat(50_000), ")".repeat(50_000));
+        assert_eq!(
+            parse_expr_from_str_with_limits(&deep, &Limits::new().with_max_depth(100)),
+            Err(ParserError::DepthLimitExceeded(100))
+        );
+
+        let limits = Limits::new().with_max_length(11);
+        assert!(parse_expr_from_str_with_limits("{a} + 1 > 2", &limits).is_ok());
+        assert_eq!(
+            parse_expr_from_str_with_limits("{a} + 1 > 2 ", &limits),
+            Err(ParserError::LengthLimitExceeded(11))
+        );
+        assert_eq!(
+            parse_expr_from_str_with_limits(&deep, &limits),
+            Err(ParserError::LengthLimitExceeded(11))
+        );
+        assert_eq!(
+            parse_expr_from_json_with_limits(json_expr, &limits),
+            Err(ParserError::LengthLimitExceeded(11))
+        );
+    }
+
+    #[test]
+    fn test_parse_identifier() {
+        assert_eq!(
+            parse_identifier("{name}"),
+            Ok(Expr::Identifier("name".to_owned()))
+        );
+        assert_eq!(
+            parse_identifier("{user.address.city}"),
+            Ok(Expr::Path(
+                "user".to_owned(),
+                vec![
+                    PathSegment::Field("address".to_owned()),
+                    PathSegment::Field("city".to_owned()),
+                ]
+            ))
+        );
+        assert_eq!(
+            parse_identifier("{items[0]}"),
+            Ok(Expr::Path("items".to_owned(), vec![PathSegment::Index(0)]))
+        );
+        assert_eq!(
+            parse_identifier("{orders[12].lines[3].sku}"),
+            Ok(Expr::Path(
+                "orders".to_owned(),
+                vec![
+                    PathSegment::Index(12),
+                    PathSegment::Field("lines".to_owned()),
+                    PathSegment::Index(3),
+                    PathSegment::Field("sku".to_owned()),
+                ]
+            ))
+        );
+
+        let index = "9".repeat(30);
+        assert_eq!(
+            parse_identifier(&format!("{{items[{}]}}", index)),
+            Err("path index out of range")
+        );
+        let e = match parse_expr_from_str(&format!("{{a}} > 1 && {{items[{}]}}", index)) {
+            Err(ParserError::InvalidSyntax(e)) => *e,
+            r => panic!("expected syntax error, got {:?}", r),
+        };
+        assert_eq!(
+            (e.message.as_str(), e.span),
+            ("path index out of range", 11..50)
+        );
+        assert_eq!(
+            parse_expr_from_json(&format!(
+                r#"{{"lhs": "{{items[{}]}}", "op": ">", "rhs": 1}}"#,
+                index
+            )),
+            Err(ParserError::InvalidExpr(format!(
+                "path index out of range: {{items[{}]}}",
+                index
+            )))
+        );
+    }
+
+    #[test]
+    fn test_parse_string_literal() {
+        assert_eq!(parse_string_literal("'hello'"), Ok("hello".to_owned()));
+        assert_eq!(parse_string_literal("\"hello\""), Ok("hello".to_owned()));
+        assert_eq!(parse_string_literal("''"), Ok("".to_owned()));
+        assert_eq!(
+            parse_string_literal(r#"'it\'s \"ok\"'"#),
+            Ok(r#"it's "ok""#.to_owned())
+        );
+        assert_eq!(
+            parse_string_literal(r"'a\nb\tc\\d'"),
+            Ok("a\nb\tc\\d".to_owned())
+        );
+        assert_eq!(
+            parse_string_literal(r"'\u{48}\u{1F600}!'"),
+            Ok("H\u{1F600}!".to_owned())
+        );
+        assert_eq!(parse_string_literal(r"'\\d+'"), Ok(r"\d+".to_owned()));
+        assert_eq!(parse_string_literal(r"'\d+'"), Err("invalid escape"));
+        assert_eq!(parse_string_literal(r"'\'"), Err("invalid escape"));
+        assert_eq!(
+            parse_string_literal(r"'\u{110000}'"),
+            Err("invalid unicode escape")
+        );
+        assert_eq!(
+            parse_string_literal(r"'\u48'"),
+            Err("invalid unicode escape")
         );
     }
 
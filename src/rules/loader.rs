@@ -0,0 +1,490 @@
+use serde::Deserialize;
+use serde_json::Value as JsonValue;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::path::Path;
+use thiserror::Error;
+
+use crate::ast::value::Value;
+use crate::checker::checker::Type;
+use crate::evaluator::limits::Limits;
+use crate::parser::parser::{
+    parse_expr_from_json_value, parse_expr_from_str_with_limits, ParserError,
+};
+use crate::rules::rules::{Rule, RuleSet};
+
+/// File format of a rule bundle. TOML and YAML are parsed only with the `toml` and `yaml`
+/// features, both enabled by default.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Json,
+    Toml,
+    Yaml,
+}
+
+impl Format {
+    /// Format named by the extension of `path`: `json`, `toml`, `yaml` or `yml`
+    pub fn from_path(path: &Path) -> Option<Format> {
+        match path.extension()?.to_str()? {
+            "json" => Some(Format::Json),
+            "toml" => Some(Format::Toml),
+            "yaml" | "yml" => Some(Format::Yaml),
+            _ => None,
+        }
+    }
+}
+
+/// Descriptive fields of a rule bundle
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Metadata {
+    pub name: Option<String>,
+    pub version: Option<String>,
+    pub description: Option<String>,
+}
+
+/// Rules loaded from a file, along with the metadata and parameter schema of the file
+#[derive(Debug, PartialEq)]
+pub struct RuleBundle {
+    pub metadata: Metadata,
+    pub parameters: BTreeMap<String, Type>,
+    pub rules: RuleSet,
+}
+
+impl RuleBundle {
+    /// Parameter schema in the form expected by `type_check`
+    pub fn schema(&self) -> HashMap<&str, Type> {
+        self.parameters
+            .iter()
+            .map(|(name, t)| (name.as_str(), t.clone()))
+            .collect()
+    }
+}
+
+#[derive(Error, Debug, PartialEq)]
+pub enum BundleError {
+    #[error("{0}: {1}")]
+    InvalidFile(String, String),
+    #[error("{0}: parameter {1}: unknown type {2}")]
+    InvalidParameterType(String, String, String),
+    #[error("{0}: rule {1}: {2}")]
+    InvalidRule(String, String, ParserError),
+    #[error("{0}: duplicate rule {1}")]
+    DuplicateRule(String, String),
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct BundleFile {
+    #[serde(default)]
+    metadata: Metadata,
+    #[serde(default)]
+    parameters: BTreeMap<String, String>,
+    #[serde(default)]
+    rules: Vec<RuleEntry>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RuleEntry {
+    id: String,
+    condition: JsonValue,
+    #[serde(default)]
+    output: Option<JsonValue>,
+    #[serde(default)]
+    priority: i64,
+    #[serde(default = "enabled")]
+    enabled: bool,
+}
+
+fn enabled() -> bool {
+    true
+}
+
+/// Load a rule bundle from a file, in the format named by its extension.
+///
+/// See `parse_rule_bundle` for the layout of the file.
+pub fn load_rule_bundle(path: impl AsRef<Path>) -> Result<RuleBundle, Vec<BundleError>> {
+    load_rule_bundle_with_limits(path, &Limits::default())
+}
+
+/// Load a rule bundle from a file, rejecting conditions longer, nested deeper or with more
+/// nodes than `limits` allow
+pub fn load_rule_bundle_with_limits(
+    path: impl AsRef<Path>,
+    limits: &Limits,
+) -> Result<RuleBundle, Vec<BundleError>> {
+    let path = path.as_ref();
+    let file = path.display().to_string();
+    let format = Format::from_path(path).ok_or_else(|| {
+        vec![BundleError::InvalidFile(
+            file.clone(),
+            "unknown format, expected a .json, .toml, .yaml or .yml file".to_owned(),
+        )]
+    })?;
+    let source = std::fs::read_to_string(path)
+        .map_err(|e| vec![BundleError::InvalidFile(file.clone(), e.to_string())])?;
+
+    parse_rule_bundle_with_limits(&file, &source, format, limits)
+}
+
+/// Parse a rule bundle from `source`, naming it `file` in errors.
+///
+/// A bundle has optional `metadata` (`name`, `version`, `description`), a `parameters` schema
+/// mapping parameter names to types such as `number` or `array<string>`, and a list of `rules`
+/// with an `id`, a `condition`, and optionally an `output`, a `priority` and an `enabled` flag.
+/// A condition is either a string in the `parse_expr_from_str` syntax or an expression in the
+/// `parse_expr_from_json` format.
+///
+/// Every rule is parsed, and all the errors are reported together.
+///
+/// # Examples
+///
+/// ```
+/// use evaluator_rs::*;
+///
+/// # #[cfg(feature = "yaml")] {
+/// let source = r#"
+/// metadata:
+///   name: discounts
+/// parameters:
+///   total: number
+///   member: bool
+/// rules:
+///   - id: vip
+///     condition: "{total} > 1000"
+///     output: 0.2
+///     priority: 10
+///   - id: member
+///     condition: { lhs: "{member}", op: "==", rhs: true }
+///     output: 0.05
+/// "#;
+/// let bundle = parse_rule_bundle("discounts.yaml", source, Format::Yaml).unwrap();
+/// assert_eq!(bundle.metadata.name.as_deref(), Some("discounts"));
+/// assert_eq!(bundle.rules.len(), 2);
+/// # }
+///
+/// let source = r#"{ "rules": [{ "id": "broken", "condition": "{total} >" }] }"#;
+/// let errors = parse_rule_bundle("broken.json", source, Format::Json).unwrap_err();
+/// assert!(errors[0].to_string().starts_with("broken.json: rule broken: Invalid syntax"));
+/// ```
+pub fn parse_rule_bundle(
+    file: &str,
+    source: &str,
+    format: Format,
+) -> Result<RuleBundle, Vec<BundleError>> {
+    parse_rule_bundle_with_limits(file, source, format, &Limits::default())
+}
+
+/// Parse a rule bundle from `source`, rejecting conditions longer, nested deeper or with more
+/// nodes than `limits` allow as `parse_expr_from_str_with_limits` does. The length only applies
+/// to conditions written as strings.
+///
+/// # Examples
+///
+/// ```
+/// use evaluator_rs::*;
+///
+/// let source = r#"{ "rules": [{ "id": "deep", "condition": "-(-(-{a}))" }] }"#;
+/// let limits = Limits::new().with_max_depth(3);
+/// let errors = parse_rule_bundle_with_limits("deep.json", source, Format::Json, &limits)
+///     .unwrap_err();
+/// assert_eq!(
+///     errors,
+///     [BundleError::InvalidRule(
+///         "deep.json".to_owned(),
+///         "deep".to_owned(),
+///         ParserError::DepthLimitExceeded(3)
+///     )]
+/// );
+/// ```
+pub fn parse_rule_bundle_with_limits(
+    file: &str,
+    source: &str,
+    format: Format,
+    limits: &Limits,
+) -> Result<RuleBundle, Vec<BundleError>> {
+    let bundle: BundleFile = match format {
+        Format::Json => serde_json::from_str(source).map_err(|e| e.to_string()),
+        #[cfg(feature = "toml")]
+        Format::Toml => toml::from_str(source).map_err(|e| e.to_string()),
+        #[cfg(not(feature = "toml"))]
+        Format::Toml => Err("TOML bundles require the `toml` feature".to_owned()),
+        #[cfg(feature = "yaml")]
+        Format::Yaml => serde_norway::from_str(source).map_err(|e| e.to_string()),
+        #[cfg(not(feature = "yaml"))]
+        Format::Yaml => Err("YAML bundles require the `yaml` feature".to_owned()),
+    }
+    .map_err(|e| {
+        vec![BundleError::InvalidFile(
+            file.to_owned(),
+            e.trim().to_owned(),
+        )]
+    })?;
+
+    let mut errors = vec![];
+    let mut parameters = BTreeMap::new();
+    for (name, t) in bundle.parameters {
+        match t.parse() {
+            Ok(t) => {
+                parameters.insert(name, t);
+            }
+            Err(t) => errors.push(BundleError::InvalidParameterType(file.to_owned(), name, t)),
+        }
+    }
+
+    let mut rules = RuleSet::new();
+    let mut ids = HashSet::new();
+    for entry in bundle.rules {
+        if !ids.insert(entry.id.clone()) {
+            errors.push(BundleError::DuplicateRule(file.to_owned(), entry.id));
+            continue;
+        }
+
+        let condition = match &entry.condition {
+            JsonValue::String(s) => parse_expr_from_str_with_limits(s, limits),
+            v => parse_expr_from_json_value(v, limits),
+        };
+        match condition {
+            Ok(condition) => {
+                let mut rule = Rule::new(&entry.id, condition)
+                    .with_priority(entry.priority)
+                    .with_enabled(entry.enabled);
+                rule.output = entry.output.as_ref().map(Value::from);
+                rules.insert(rule);
+            }
+            Err(e) => errors.push(BundleError::InvalidRule(file.to_owned(), entry.id, e)),
+        }
+    }
+
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+
+    Ok(RuleBundle {
+        metadata: bundle.metadata,
+        parameters,
+        rules,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parser;
+
+    fn rule(id: &str, condition: &str) -> Rule {
+        Rule::new(id, parser::parse_expr_from_str(condition).unwrap())
+    }
+
+    fn bundle() -> RuleBundle {
+        RuleBundle {
+            metadata: Metadata {
+                name: Some("shipping".to_owned()),
+                version: Some("2".to_owned()),
+                description: None,
+            },
+            parameters: BTreeMap::from([
+                ("country".to_owned(), Type::String),
+                ("weight".to_owned(), Type::Number),
+            ]),
+            rules: RuleSet::new()
+                .with_rule(
+                    rule("heavy", "{weight} > 30")
+                        .with_output(Value::from("freight"))
+                        .with_priority(10),
+                )
+                .with_rule(
+                    rule("domestic", "{country} == 'FR'")
+                        .with_output(Value::from("standard"))
+                        .with_enabled(false),
+                ),
+        }
+    }
+
+    #[test]
+    fn test_parse_rule_bundle() {
+        let json = r#"{
+            "metadata": { "name": "shipping", "version": "2" },
+            "parameters": { "weight": "number", "country": "string" },
+            "rules": [
+                { "id": "domestic", "condition": { "lhs": "{country}", "op": "==", "rhs": "FR" },
+                  "output": "standard", "enabled": false },
+                { "id": "heavy", "condition": "{weight} > 30", "output": "freight", "priority": 10 }
+            ]
+        }"#;
+        let toml = r#"
+            [metadata]
+            name = "shipping"
+            version = "2"
+
+            [parameters]
+            weight = "number"
+            country = "string"
+
+            [[rules]]
+            id = "domestic"
+            condition = { lhs = "{country}", op = "==", rhs = "FR" }
+            output = "standard"
+            enabled = false
+
+            [[rules]]
+            id = "heavy"
+            condition = "{weight} > 30"
+            output = "freight"
+            priority = 10
+        "#;
+        let yaml = r#"
+            metadata:
+              name: shipping
+              version: "2"
+            parameters:
+              weight: number
+              country: string
+            rules:
+              - id: domestic
+                condition:
+                  lhs: "{country}"
+                  op: "=="
+                  rhs: FR
+                output: standard
+                enabled: false
+              - id: heavy
+                condition: "{weight} > 30"
+                output: freight
+                priority: 10
+        "#;
+        let mut test_cases = vec![(json, Format::Json)];
+        #[cfg(feature = "toml")]
+        test_cases.push((toml, Format::Toml));
+        #[cfg(feature = "yaml")]
+        test_cases.push((yaml, Format::Yaml));
+        test_cases.into_iter().for_each(|(source, format)| {
+            assert_eq!(
+                parse_rule_bundle("shipping", source, format),
+                Ok(bundle()),
+                "format: {:?}",
+                format
+            );
+        });
+    }
+
+    #[test]
+    #[cfg(feature = "yaml")]
+    fn test_parse_rule_bundle_errors() {
+        let source = r#"
+            parameters:
+              weight: number
+              country: text
+            rules:
+              - id: heavy
+                condition: "{weight} >"
+              - id: heavy
+                condition: "true"
+              - id: ok
+                condition: "{weight} > 1"
+              - id: bad-op
+                condition: { lhs: 1, op: "**", rhs: 2 }
+        "#;
+        let errors = parse_rule_bundle("rules.yaml", source, Format::Yaml).unwrap_err();
+        let messages: Vec<String> = errors.iter().map(|e| e.to_string()).collect();
+        assert_eq!(messages.len(), 4, "errors: {:?}", messages);
+        assert_eq!(
+            messages[0],
+            "rules.yaml: parameter country: unknown type text"
+        );
+        assert!(messages[1]
+            .starts_with("rules.yaml: rule heavy: Invalid syntax: unexpected end of input"));
+        assert_eq!(messages[2], "rules.yaml: duplicate rule heavy");
+        assert_eq!(messages[3], "rules.yaml: rule bad-op: Invalid op: **");
+
+        #[cfg(feature = "toml")]
+        {
+            let errors =
+                parse_rule_bundle("rules.toml", "[[rules]]\nid = 1", Format::Toml).unwrap_err();
+            assert!(
+                matches!(&errors[..], [BundleError::InvalidFile(file, _)] if file == "rules.toml"),
+                "errors: {:?}",
+                errors
+            );
+        }
+        let errors =
+            parse_rule_bundle("rules.json", r#"{ "rules": [], "extra": 1 }"#, Format::Json)
+                .unwrap_err();
+        assert!(
+            errors[0].to_string().contains("unknown field `extra`"),
+            "errors: {:?}",
+            errors
+        );
+    }
+
+    #[test]
+    fn test_parse_rule_bundle_with_limits() {
+        let source = r#"{ "rules": [
+            { "id": "long", "condition": "{weight} > 30 && {country} == 'FR'" },
+            { "id": "deep", "condition": { "op": "!", "rhs": { "op": "!", "rhs": true } } },
+            { "id": "ok", "condition": "{weight} > 30" }
+        ] }"#;
+        let limits = Limits::new().with_max_length(20).with_max_depth(2);
+        let errors =
+            parse_rule_bundle_with_limits("rules.json", source, Format::Json, &limits).unwrap_err();
+        assert_eq!(
+            errors,
+            [
+                BundleError::InvalidRule(
+                    "rules.json".to_owned(),
+                    "long".to_owned(),
+                    ParserError::LengthLimitExceeded(20)
+                ),
+                BundleError::InvalidRule(
+                    "rules.json".to_owned(),
+                    "deep".to_owned(),
+                    ParserError::DepthLimitExceeded(2)
+                ),
+            ]
+        );
+        assert!(std::error::Error::source(&errors[0]).is_none());
+        assert!(parse_rule_bundle("rules.json", source, Format::Json).is_ok());
+    }
+
+    #[test]
+    #[cfg(not(feature = "yaml"))]
+    fn test_parse_rule_bundle_without_yaml() {
+        let errors = parse_rule_bundle("rules.yaml", "rules: []", Format::Yaml).unwrap_err();
+        assert_eq!(
+            errors,
+            [BundleError::InvalidFile(
+                "rules.yaml".to_owned(),
+                "YAML bundles require the `yaml` feature".to_owned()
+            )]
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "yaml")]
+    fn test_load_rule_bundle() {
+        let dir = std::env::temp_dir().join(format!("evaluator_rs_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("shipping.yml");
+        std::fs::write(
+            &path,
+            "rules:\n  - id: heavy\n    condition: '{weight} > 30'\n",
+        )
+        .unwrap();
+
+        let bundle = load_rule_bundle(&path).unwrap();
+        assert_eq!(bundle.rules.rules(), [rule("heavy", "{weight} > 30")]);
+        assert_eq!(bundle.metadata, Metadata::default());
+
+        let missing = dir.join("missing.json");
+        let errors = load_rule_bundle(&missing).unwrap_err();
+        assert!(
+            matches!(&errors[..], [BundleError::InvalidFile(file, _)] if *file == missing.display().to_string()),
+            "errors: {:?}",
+            errors
+        );
+        let errors = load_rule_bundle(dir.join("rules.txt")).unwrap_err();
+        assert!(errors[0].to_string().contains("unknown format"));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
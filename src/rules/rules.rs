@@ -0,0 +1,315 @@
+use thiserror::Error;
+
+use crate::ast::{expr::Expr, value::Value};
+use crate::evaluator::context::Context;
+use crate::evaluator::evaluator::{
+    with_default_evaluator, Evaluator, EvaluatorError, LocatedError,
+};
+
+/// Named condition with an optional output value.
+///
+/// Rules are enabled with priority 0 unless configured otherwise; rules with a higher priority
+/// are evaluated first.
+#[derive(Debug, PartialEq)]
+pub struct Rule {
+    pub id: String,
+    pub condition: Box<Expr>,
+    pub output: Option<Value>,
+    pub priority: i64,
+    pub enabled: bool,
+}
+
+impl Rule {
+    pub fn new(id: &str, condition: Box<Expr>) -> Self {
+        Rule {
+            id: id.to_owned(),
+            condition,
+            output: None,
+            priority: 0,
+            enabled: true,
+        }
+    }
+
+    pub fn with_output(mut self, output: Value) -> Self {
+        self.output = Some(output);
+        self
+    }
+
+    pub fn with_priority(mut self, priority: i64) -> Self {
+        self.priority = priority;
+        self
+    }
+
+    pub fn with_enabled(mut self, enabled: bool) -> Self {
+        self.enabled = enabled;
+        self
+    }
+}
+
+/// How the rules of a `RuleSet` are evaluated
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Strategy {
+    /// Stop at the first rule that matches, by priority
+    FirstMatch,
+    /// Evaluate every rule and report all the ones that match
+    AllMatches,
+    /// Evaluate the rules that have an output and report all the ones that match
+    CollectOutputs,
+}
+
+/// Rules kept in evaluation order: by decreasing priority, then in the order they were added.
+///
+/// # Examples
+///
+/// ```
+/// use evaluator_rs::*;
+/// use std::collections::HashMap;
+///
+/// let rules = RuleSet::new()
+///     .with_rule(
+///         Rule::new("vip", parse_expr_from_str("{total} > 1000").unwrap())
+///             .with_output(Value::from(0.2))
+///             .with_priority(10),
+///     )
+///     .with_rule(
+///         Rule::new("member", parse_expr_from_str("{member}").unwrap())
+///             .with_output(Value::from(0.05)),
+///     );
+///
+/// let parameters = HashMap::from([("total", Value::from(1500)), ("member", Value::from(true))]);
+/// let rs = evaluate_rules(&rules, &parameters, Strategy::FirstMatch);
+/// assert_eq!(rs.fired_ids(), ["vip"]);
+/// assert_eq!(rs.outputs(), [&Value::from(0.2)]);
+///
+/// let rs = evaluate_rules(&rules, &parameters, Strategy::AllMatches);
+/// assert_eq!(rs.fired_ids(), ["vip", "member"]);
+/// ```
+#[derive(Debug, Default, PartialEq)]
+pub struct RuleSet {
+    rules: Vec<Rule>,
+}
+
+impl RuleSet {
+    pub fn new() -> Self {
+        RuleSet::default()
+    }
+
+    /// Add a rule, replacing any earlier rule with the same id
+    pub fn with_rule(mut self, rule: Rule) -> Self {
+        self.insert(rule);
+        self
+    }
+
+    /// Add a rule, returning the earlier rule with the same id if any
+    pub fn insert(&mut self, rule: Rule) -> Option<Rule> {
+        let replaced = self.remove(&rule.id);
+        let index = self.rules.partition_point(|r| r.priority >= rule.priority);
+        self.rules.insert(index, rule);
+        replaced
+    }
+
+    pub fn remove(&mut self, id: &str) -> Option<Rule> {
+        let index = self.rules.iter().position(|r| r.id == id)?;
+        Some(self.rules.remove(index))
+    }
+
+    pub fn get(&self, id: &str) -> Option<&Rule> {
+        self.rules.iter().find(|r| r.id == id)
+    }
+
+    /// Rules in evaluation order
+    pub fn rules(&self) -> &[Rule] {
+        &self.rules
+    }
+
+    pub fn len(&self) -> usize {
+        self.rules.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+}
+
+/// Rule that matched, with its output
+#[derive(Debug, Clone, PartialEq)]
+pub struct FiredRule {
+    pub id: String,
+    pub output: Option<Value>,
+}
+
+/// Error evaluating the condition of a rule; its message includes the evaluator error and the
+/// subexpression that failed
+#[derive(Error, Debug, PartialEq)]
+#[error("rule {id}: {error}")]
+pub struct RuleError {
+    pub id: String,
+    pub error: LocatedError,
+}
+
+/// Rules that matched, in evaluation order, and errors of the rules that could not be evaluated
+#[derive(Debug, Default, PartialEq)]
+pub struct RuleSetResult {
+    pub fired: Vec<FiredRule>,
+    pub errors: Vec<RuleError>,
+}
+
+impl RuleSetResult {
+    pub fn fired_ids(&self) -> Vec<&str> {
+        self.fired.iter().map(|r| r.id.as_str()).collect()
+    }
+
+    /// Outputs of the rules that matched, skipping rules without output
+    pub fn outputs(&self) -> Vec<&Value> {
+        self.fired
+            .iter()
+            .filter_map(|r| r.output.as_ref())
+            .collect()
+    }
+}
+
+impl Evaluator {
+    /// Evaluate the enabled rules of `rules` with parameters.
+    ///
+    /// A condition matches when it evaluates to `true`. Conditions that fail or evaluate to
+    /// anything but a boolean are reported in `errors` and evaluation goes on with the next
+    /// rule.
+    pub fn evaluate_rules<C: Context + ?Sized>(
+        &self,
+        rules: &RuleSet,
+        parameters: &C,
+        strategy: Strategy,
+    ) -> RuleSetResult {
+        let mut result = RuleSetResult::default();
+        let candidates = rules.rules.iter().filter(|rule| {
+            rule.enabled && (strategy != Strategy::CollectOutputs || rule.output.is_some())
+        });
+
+        for rule in candidates {
+            match self.evaluate(&rule.condition, parameters) {
+                Ok(Value::Bool(true)) => {
+                    result.fired.push(FiredRule {
+                        id: rule.id.clone(),
+                        output: rule.output.clone(),
+                    });
+                    if strategy == Strategy::FirstMatch {
+                        break;
+                    }
+                }
+                Ok(Value::Bool(false)) => {}
+                Ok(v) => result.errors.push(RuleError {
+                    id: rule.id.clone(),
+                    error: EvaluatorError::UnexpectedType("bool".to_owned(), v).into(),
+                }),
+                Err(error) => result.errors.push(RuleError {
+                    id: rule.id.clone(),
+                    error,
+                }),
+            }
+        }
+
+        result
+    }
+}
+
+/// Evaluate rules with the built-in functions
+pub fn evaluate_rules<C: Context + ?Sized>(
+    rules: &RuleSet,
+    parameters: &C,
+    strategy: Strategy,
+) -> RuleSetResult {
+    with_default_evaluator(|evaluator| evaluator.evaluate_rules(rules, parameters, strategy))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::expr::{NodePath, NodeStep};
+    use crate::parser::parser;
+    use std::collections::HashMap;
+
+    fn rule(id: &str, condition: &str) -> Rule {
+        Rule::new(id, parser::parse_expr_from_str(condition).unwrap())
+    }
+
+    #[test]
+    fn test_rule_set() {
+        let mut rules = RuleSet::new()
+            .with_rule(rule("low", "true"))
+            .with_rule(rule("high", "true").with_priority(10))
+            .with_rule(rule("mid-1", "true").with_priority(5))
+            .with_rule(rule("mid-2", "true").with_priority(5));
+        let ids: Vec<&str> = rules.rules().iter().map(|r| r.id.as_str()).collect();
+        assert_eq!(ids, ["high", "mid-1", "mid-2", "low"]);
+
+        let replaced = rules.insert(rule("mid-1", "false").with_priority(-1));
+        assert_eq!(replaced, Some(rule("mid-1", "true").with_priority(5)));
+        let ids: Vec<&str> = rules.rules().iter().map(|r| r.id.as_str()).collect();
+        assert_eq!(ids, ["high", "mid-2", "low", "mid-1"]);
+
+        assert_eq!(rules.remove("high").map(|r| r.priority), Some(10));
+        assert_eq!(rules.get("high"), None);
+        assert_eq!(rules.len(), 3);
+    }
+
+    #[test]
+    fn test_evaluate_rules() {
+        let rules = RuleSet::new()
+            .with_rule(rule("broken", "{missing} > 1").with_priority(20))
+            .with_rule(rule("not-bool", "{amount} + 1").with_priority(15))
+            .with_rule(
+                rule("large", "{amount} > 100")
+                    .with_output(Value::from("review"))
+                    .with_priority(10),
+            )
+            .with_rule(rule("flagged", "{flagged}").with_priority(10))
+            .with_rule(
+                rule("disabled", "true")
+                    .with_enabled(false)
+                    .with_priority(30),
+            )
+            .with_rule(rule("any", "{amount} > 0").with_output(Value::from("accept")));
+        let parameters =
+            HashMap::from([("amount", Value::from(150)), ("flagged", Value::from(true))]);
+        let errors = vec![
+            RuleError {
+                id: "broken".to_owned(),
+                error: LocatedError {
+                    error: EvaluatorError::InvalidParameter("missing".to_owned()),
+                    path: NodePath(vec![NodeStep::Lhs]),
+                },
+            },
+            RuleError {
+                id: "not-bool".to_owned(),
+                error: EvaluatorError::UnexpectedType("bool".to_owned(), Value::from(151)).into(),
+            },
+        ];
+
+        let rs = evaluate_rules(&rules, &parameters, Strategy::FirstMatch);
+        assert_eq!(rs.fired_ids(), ["large"]);
+        assert_eq!(rs.errors, errors);
+
+        let rs = evaluate_rules(&rules, &parameters, Strategy::AllMatches);
+        assert_eq!(rs.fired_ids(), ["large", "flagged", "any"]);
+        assert_eq!(
+            rs.outputs(),
+            [&Value::from("review"), &Value::from("accept")]
+        );
+        assert_eq!(rs.errors, errors);
+
+        let rs = evaluate_rules(&rules, &parameters, Strategy::CollectOutputs);
+        assert_eq!(rs.fired_ids(), ["large", "any"]);
+        assert_eq!(rs.errors, vec![]);
+
+        let parameters =
+            HashMap::from([("amount", Value::from(0)), ("flagged", Value::from(false))]);
+        let rs = evaluate_rules(&rules, &parameters, Strategy::FirstMatch);
+        assert_eq!(rs.fired, vec![]);
+        assert_eq!(rs.errors.len(), 2);
+        assert_eq!(
+            rs.errors[0].to_string(),
+            "rule broken: invalid parameter missing at /lhs"
+        );
+        assert!(std::error::Error::source(&rs.errors[0]).is_none());
+    }
+}
@@ -0,0 +1,43 @@
+//! Deeply nested expressions shared by the tests of every pass
+
+use std::collections::HashMap;
+
+use crate::ast::value::Value;
+
+/// Depth of the expressions evaluated and dropped, as deep as machine-generated rules get
+pub(crate) const EVALUATE_DEPTH: usize = 100_000;
+
+/// Depth of the expressions given to the other passes, enough to overflow the stack of a
+/// recursive implementation
+pub(crate) const PASS_DEPTH: usize = 10_000;
+
+/// Parameters of the expressions of `deep_exprs`
+pub(crate) fn deep_parameters() -> HashMap<&'static str, Value> {
+    HashMap::from([
+        ("a", Value::from(false)),
+        ("n", Value::from(1)),
+        ("s", Value::from("x")),
+    ])
+}
+
+/// Source of a left-leaning chain of `depth` clauses `operand || operand || …`
+pub(crate) fn chain(operand: &str, depth: usize) -> String {
+    vec![operand; depth].join(" || ")
+}
+
+/// Expressions nested `depth` deep in each way an operand nests, with their value under
+/// `deep_parameters`: a chain of `||`, negations, `else` branches and calls. `depth` is even.
+pub(crate) fn deep_exprs(depth: usize) -> Vec<(String, Value)> {
+    vec![
+        (chain("{a}", depth), Value::from(false)),
+        (
+            format!("{}{{n}}{}", "-(".repeat(depth), ")".repeat(depth)),
+            Value::from(1),
+        ),
+        ("if {a} then 0 else ".repeat(depth) + "1", Value::from(1)),
+        (
+            format!("{}{{s}}{}", "upper(".repeat(depth), ")".repeat(depth)),
+            Value::from("X"),
+        ),
+    ]
+}